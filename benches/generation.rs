@@ -0,0 +1,111 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use qrust::create_qr_code;
+use qrust::layout_code;
+use qrust::qr::encode::{QRBitstreamEncoder, QREncoding};
+use qrust::qr::error_correction::{bitstream_with_ec, ErrorCorrectionLevel};
+use qrust::qr::pattern::MaskSelection;
+use qrust::qr::version::{choose_version, Version};
+
+const VERSIONS: [usize; 4] = [1, 10, 25, 40];
+const ECLS: [ErrorCorrectionLevel; 4] = [
+    ErrorCorrectionLevel::Low,
+    ErrorCorrectionLevel::Medium,
+    ErrorCorrectionLevel::Quartile,
+    ErrorCorrectionLevel::High,
+];
+
+/// The longest run of digits that still fits in the version at the ECL, so generation lands on
+/// exactly that version.
+fn digits_for(version: usize, ecl: ErrorCorrectionLevel) -> String {
+    let digits = |count: usize| "0123456789".chars().cycle().take(count).collect::<String>();
+    let fits = |count: usize| {
+        choose_version(&QRBitstreamEncoder::new(&digits(count)), &ecl)
+            .is_ok_and(|chosen| chosen.num as usize <= version)
+    };
+    let (mut low, mut high) = (1usize, 7089);
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        if fits(middle) {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    digits(low)
+}
+
+fn end_to_end(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_qr_code");
+    for &version in VERSIONS.iter() {
+        for &ecl in ECLS.iter() {
+            let data = digits_for(version, ecl);
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", ecl), version),
+                &data,
+                |b, data| b.iter(|| create_qr_code(black_box(data), ecl).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn numeric_encoding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("numeric_encoding");
+    for &length in &[100, 1000, 5000, 7089] {
+        let data = "0123456789"
+            .chars()
+            .cycle()
+            .take(length)
+            .collect::<String>();
+        group.bench_with_input(BenchmarkId::from_parameter(length), &data, |b, data| {
+            b.iter(|| QRBitstreamEncoder::with_mode(black_box(data), QREncoding::Numeric).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn error_correction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bitstream_with_ec");
+    for &version in VERSIONS.iter() {
+        let ecl = ErrorCorrectionLevel::Medium;
+        let version_data = Version::by_num(version).values_at_ecl(&ecl);
+        let codewords = QRBitstreamEncoder::new(&digits_for(version, ecl))
+            .codewords(Version::by_num(version), &ecl)
+            .unwrap();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(version),
+            &codewords,
+            |b, data| b.iter(|| bitstream_with_ec(black_box(data.clone()), version_data).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn mask_selection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("select_mask");
+    for &version in VERSIONS.iter() {
+        let ecl = ErrorCorrectionLevel::Medium;
+        let mut encoder = QRBitstreamEncoder::new(&digits_for(version, ecl));
+        let chosen = Version::by_num(version);
+        let codewords = encoder.codewords(chosen, &ecl).unwrap();
+        let data_with_ec = bitstream_with_ec(codewords, chosen.values_at_ecl(&ecl)).unwrap();
+        let code = layout_code(&encoder, chosen, ecl, data_with_ec);
+        for &selection in &[MaskSelection::Full, MaskSelection::Fast] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", selection), version),
+                &code,
+                |b, code| b.iter(|| code.select_mask(selection)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    end_to_end,
+    numeric_encoding,
+    error_correction,
+    mask_selection
+);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+//! Feeds arbitrary bytes through the image loader and into the decoder, which should only ever
+//! return errors for them, never panic. Run with `cargo fuzz run from_image`.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use qrust::qr::decode::from_image;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(img) = image::load_from_memory(data) {
+        let _ = from_image(&img.to_rgb8(), None);
+    }
+});
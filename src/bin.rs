@@ -1,12 +1,17 @@
 use std::fs::File;
 use std::io;
 use std::path::PathBuf;
+use std::str::FromStr;
 
+use serde_json::json;
 use structopt::StructOpt;
 
-use qrust::create_qr_code;
 use qrust::qr::error_correction::ErrorCorrectionLevel;
+use qrust::qr::image::RenderOptions;
+use qrust::qr::info::describe;
+use qrust::qr::pattern::QRCode;
 use qrust::qr::Error;
+use qrust::{create_qr_code, QRCodeBuilder};
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "qrgen", about = "Generate a QR code")]
@@ -28,9 +33,85 @@ struct Opts {
     #[structopt(
         short = "e",
         long = "ecl",
-        help = "Error correction level (low, medium, quartile or high; default medium)"
+        help = "Error correction level (low, medium, quartile or high, or 0-3; default medium)"
     )]
     ecl: Option<ErrorCorrectionLevel>,
+
+    #[structopt(
+        long = "input-encoding",
+        default_value = "raw",
+        help = "How the input is written: raw text, or hex or base64 for binary data"
+    )]
+    input_encoding: InputEncoding,
+
+    #[structopt(
+        long = "no-trim",
+        help = "Encode raw input exactly as given, without trimming leading and trailing whitespace"
+    )]
+    no_trim: bool,
+
+    #[structopt(
+        long = "info",
+        help = "Print the version and codeword usage of the code"
+    )]
+    info: bool,
+
+    #[structopt(
+        long = "json",
+        help = "Print the code's details as a JSON object, for scripts; takes the place of --info"
+    )]
+    json: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InputEncoding {
+    Raw,
+    Hex,
+    Base64,
+}
+
+impl FromStr for InputEncoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "raw" => Ok(InputEncoding::Raw),
+            "hex" => Ok(InputEncoding::Hex),
+            "base64" => Ok(InputEncoding::Base64),
+            _ => Err(format!("Unknown input encoding {}", s).into()),
+        }
+    }
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, Error> {
+    if !text.len().is_multiple_of(2) {
+        return Err("The hex input has an odd number of digits".into());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("Invalid hex digits at offset {}", i).into())
+        })
+        .collect()
+}
+
+fn json_info(code: &QRCode) -> serde_json::Value {
+    let (width, height) = RenderOptions::default().image_dimensions(code.version);
+    let stats = code.stats.as_ref();
+    json!({
+        "version": code.version.num,
+        "ecl": stats.map(|stats| format!("{:?}", stats.ecl)),
+        "mode": stats.map(|stats| format!("{:?}", stats.encoding)),
+        "mask": code.mask,
+        "data_codewords": stats.map(|stats| stats.codewords_before_padding + stats.padding_codewords),
+        "used_codewords": stats.map(|stats| stats.codewords_before_padding),
+        "padding_codewords": stats.map(|stats| stats.padding_codewords),
+        "ec_codewords": stats.map(|stats| stats.ec_codewords),
+        "width": width,
+        "height": height,
+    })
 }
 
 fn run(opts: Opts) -> Result<(), Error> {
@@ -42,9 +123,27 @@ fn run(opts: Opts) -> Result<(), Error> {
     input_stream
         .read_to_string(&mut data)
         .map_err(|e| e.to_string())?;
-    let input = data.trim();
     let ecl = opts.ecl.unwrap_or(ErrorCorrectionLevel::Medium);
-    create_qr_code(input, ecl)?.save(opts.output.as_path())
+    // binary input may be wrapped across lines, so all whitespace is dropped
+    let compact: String = data.split_whitespace().collect();
+    let code = match opts.input_encoding {
+        InputEncoding::Raw if opts.no_trim => create_qr_code(&data, ecl)?,
+        InputEncoding::Raw => create_qr_code(data.trim(), ecl)?,
+        InputEncoding::Hex => QRCodeBuilder::from_bytes(&decode_hex(&compact)?)
+            .ecl(ecl)
+            .build()?,
+        InputEncoding::Base64 => {
+            QRCodeBuilder::from_bytes(&base64::decode(&compact).map_err(|e| e.to_string())?)
+                .ecl(ecl)
+                .build()?
+        }
+    };
+    if opts.json {
+        println!("{}", json_info(&code));
+    } else if opts.info {
+        print!("{}", describe(&code));
+    }
+    code.save(opts.output.as_path())
 }
 
 pub fn main() {
@@ -1,23 +1,1348 @@
-use crate::qr::encode::QRBitstreamEncoder;
-use crate::qr::error_correction::{bitstream_with_ec, ErrorCorrectionLevel};
-use crate::qr::pattern::QRCode;
-use crate::qr::version::choose_version;
-use crate::qr::Error;
+use crate::qr::encode::{
+    needs_utf8_eci, ByteCharset, QRBitstreamEncoder, StructuredAppend, UTF8_ECI,
+};
+use crate::qr::error_correction::{
+    bitstream_with_ec, interleaved_data_order, ErrorCorrectionLevel,
+};
+use crate::qr::image::{render, render_themed, to_png, RenderOptions, Theme};
+use crate::qr::pattern::{obscurable_modules, GenerationStats, MaskSelection, QRCode};
+use crate::qr::payload;
+use crate::qr::version::{choose_version, fits, Version, MAX_BYTES_MODE_BYTES, MAX_INPUT_BYTES};
+use crate::qr::{Error, QREncodedData};
+use image::RgbImage;
+use std::time::{Instant, SystemTime};
 
 pub mod qr;
 
+/// The barcode symbologies this crate can generate, for tooling that checks capabilities. Only
+/// QR codes: not Micro QR, Aztec, DataMatrix or any other 2D barcode.
+pub fn supported_symbologies() -> &'static [&'static str] {
+    &["QR"]
+}
+
 pub fn create_qr_code(data: &str, ecl: ErrorCorrectionLevel) -> Result<QRCode, Error> {
-    let mut encoder = QRBitstreamEncoder::new(data);
-    let version = choose_version(&encoder, &ecl)?;
-    let version_ecl_data = version.values_at_ecl(&ecl);
+    QRCodeBuilder::new(data).ecl(ecl).build()
+}
+
+/// Generates a code at the given version instead of the smallest that fits, for a fixed physical
+/// size. Errors if there's no such version or the data doesn't fit in it.
+pub fn create_qr_code_with_version(
+    data: &str,
+    ecl: ErrorCorrectionLevel,
+    version_num: u8,
+) -> Result<QRCode, Error> {
+    QRCodeBuilder::new(data)
+        .ecl(ecl)
+        .version(version_num)
+        .build()
+}
+
+/// Generates a code for binary data, such as a protobuf message or a compressed blob, encoding the
+/// bytes as-is in Bytes mode.
+pub fn create_qr_code_bytes(data: &[u8], ecl: ErrorCorrectionLevel) -> Result<QRCode, Error> {
+    QRCodeBuilder::from_bytes(data).ecl(ecl).build()
+}
+
+/// Generates a code and returns it as a base64 encoded PNG, without any data URI prefix.
+pub fn create_qr_base64_png(
+    data: &str,
+    ecl: ErrorCorrectionLevel,
+    module_size: u32,
+) -> Result<String, Error> {
+    let code = create_qr_code(data, ecl)?;
+    Ok(base64::encode(to_png(
+        &code,
+        &RenderOptions::square(module_size),
+    )?))
+}
+
+/// Generates a code and renders it with the theme's colors and shapes.
+pub fn create_themed_qr(
+    data: &str,
+    ecl: ErrorCorrectionLevel,
+    theme: &Theme,
+) -> Result<RgbImage, Error> {
+    let code = create_qr_code(data, ecl)?;
+    Ok(render_themed(&code, theme, &RenderOptions::default()))
+}
+
+/// Generates a code and renders it, returning both so callers needing the module grid as well as
+/// the image don't have to generate it twice.
+pub fn create_qr_full(
+    data: &str,
+    ecl: ErrorCorrectionLevel,
+    options: &RenderOptions,
+) -> Result<(QRCode, RgbImage), Error> {
+    let code = create_qr_code(data, ecl)?;
+    let img = render(&code, options);
+    Ok((code, img))
+}
+
+/// Generates a code for a calendar event, which phone calendar apps offer to add when scanned.
+/// Times are written in UTC.
+pub fn create_event_qr(
+    summary: &str,
+    start: SystemTime,
+    end: SystemTime,
+    location: Option<&str>,
+    ecl: ErrorCorrectionLevel,
+) -> Result<QRCode, Error> {
+    create_qr_code(&payload::event(summary, start, end, location)?, ecl)
+}
+
+/// Generates a code for a geo: URI, which map apps open when scanned. Coordinates are in degrees
+/// and must be in range.
+pub fn create_geo_qr(
+    latitude: f64,
+    longitude: f64,
+    ecl: ErrorCorrectionLevel,
+) -> Result<QRCode, Error> {
+    create_qr_code(&payload::geo(latitude, longitude)?, ecl)
+}
+
+/// Generates a code for a mailto: URI, which mail apps open as a draft when scanned.
+pub fn create_mailto_qr(
+    to: &str,
+    subject: Option<&str>,
+    body: Option<&str>,
+    ecl: ErrorCorrectionLevel,
+) -> Result<QRCode, Error> {
+    create_qr_code(&payload::mailto(to, subject, body), ecl)
+}
+
+/// Generates a code for a tel: URI, which phones offer to call when scanned.
+pub fn create_tel_qr(number: &str, ecl: ErrorCorrectionLevel) -> Result<QRCode, Error> {
+    create_qr_code(&payload::tel(number)?, ecl)
+}
+
+/// Generates a code for a binary payload prefixed with its length as two big-endian bytes, as
+/// custom protocols often frame messages. The payload can be at most 2951 bytes long.
+pub fn create_qr_code_framed(payload: &[u8], ecl: ErrorCorrectionLevel) -> Result<QRCode, Error> {
+    if payload.len() > MAX_BYTES_MODE_BYTES - 2 {
+        return Err(format!(
+            "The payload is {} bytes long, more than a framed code can hold ({})",
+            payload.len(),
+            MAX_BYTES_MODE_BYTES - 2
+        )
+        .into());
+    }
+    let mut framed = Vec::with_capacity(payload.len() + 2);
+    framed.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    framed.extend_from_slice(payload);
+    QRCodeBuilder::from_bytes(&framed).ecl(ecl).build()
+}
+
+/// The most symbols a structured append set can have.
+const MAX_STRUCTURED_APPEND_SYMBOLS: usize = 16;
+
+/// Splits text too long for one code of the version and ECL across a structured append set of
+/// up to 16 codes, which readers that support it join back together. The text goes to as few
+/// codes as it fits in, divided evenly by characters so the last code isn't left nearly empty.
+/// Text that needs Bytes mode is encoded as UTF-8, the same bytes the parity is computed over.
+pub fn create_structured_append(
+    data: &str,
+    version_num: usize,
+    ecl: ErrorCorrectionLevel,
+) -> Result<Vec<QRCode>, Error> {
+    if !(1..=40).contains(&version_num) {
+        return Err(format!("There is no version {}", version_num).into());
+    }
+    let version = Version::by_num(version_num);
+    let parity = data.bytes().fold(0, |parity, byte| parity ^ byte);
+    let chars: Vec<char> = data.chars().collect();
+    for total in 1..=MAX_STRUCTURED_APPEND_SYMBOLS.min(chars.len().max(1)) {
+        let (per_symbol, extra) = (chars.len() / total, chars.len() % total);
+        let mut encoders = Vec::with_capacity(total);
+        let mut start = 0;
+        for index in 0..total {
+            // the first few symbols take one character more than the rest
+            let end = start + per_symbol + if index < extra { 1 } else { 0 };
+            let chunk: String = chars[start..end].iter().collect();
+            let header = StructuredAppend {
+                index: index as u8,
+                total: total as u8,
+                parity,
+            };
+            let encoder = QRBitstreamEncoder::with_charset(&chunk, ByteCharset::Utf8)?
+                .with_structured_append(header);
+            if !fits(&encoder, version, &ecl) {
+                break;
+            }
+            encoders.push(encoder);
+            start = end;
+        }
+        if encoders.len() == total {
+            return encoders
+                .iter_mut()
+                .map(|encoder| {
+                    build_code(
+                        encoder,
+                        version,
+                        ecl,
+                        MaskSelection::default(),
+                        Instant::now(),
+                    )
+                })
+                .collect();
+        }
+    }
+    Err(format!(
+        "The data doesn't fit in {} codes of version {} at {:?}",
+        MAX_STRUCTURED_APPEND_SYMBOLS, version_num, ecl
+    )
+    .into())
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+#[derive(Clone, Copy)]
+enum Input<'a> {
+    Text(&'a str),
+    Bytes(&'a [u8]),
+}
+
+/// Builds a QR code with more control over the encoding than `create_qr_code` offers.
+pub struct QRCodeBuilder<'a> {
+    data: Input<'a>,
+    ecl: ErrorCorrectionLevel,
+    byte_charset: ByteCharset,
+    mask_selection: MaskSelection,
+    prefer_fewer_blocks: bool,
+    append_crc32: bool,
+    strip_separators: bool,
+    reserve_center: Option<f32>,
+    version: Option<u8>,
+    eci: Option<u32>,
+    auto_eci: bool,
+}
+
+impl<'a> QRCodeBuilder<'a> {
+    pub fn new(data: &'a str) -> QRCodeBuilder<'a> {
+        QRCodeBuilder {
+            data: Input::Text(data),
+            ecl: ErrorCorrectionLevel::Medium,
+            byte_charset: ByteCharset::Auto,
+            mask_selection: MaskSelection::default(),
+            prefer_fewer_blocks: false,
+            append_crc32: false,
+            strip_separators: false,
+            reserve_center: None,
+            version: None,
+            eci: None,
+            auto_eci: true,
+        }
+    }
+
+    /// Encodes binary data as-is in Bytes mode. The byte charset doesn't apply to it.
+    pub fn from_bytes(data: &'a [u8]) -> QRCodeBuilder<'a> {
+        QRCodeBuilder {
+            data: Input::Bytes(data),
+            ..QRCodeBuilder::new("")
+        }
+    }
+
+    pub fn ecl(mut self, ecl: ErrorCorrectionLevel) -> Self {
+        self.ecl = ecl;
+        self
+    }
+
+    /// Sets the character set used when the data has to be encoded in Bytes mode.
+    pub fn byte_charset(mut self, charset: ByteCharset) -> Self {
+        self.byte_charset = charset;
+        self
+    }
+
+    /// Sets how the data mask is picked; `MaskSelection::Full` by default.
+    pub fn mask_selection(mut self, selection: MaskSelection) -> Self {
+        self.mask_selection = selection;
+        self
+    }
+
+    /// Once the version is chosen for the ECL, switches to whichever ECL the data also fits in at
+    /// that version with the fewest error correction blocks, taking the strongest of any ties.
+    /// Fewer blocks are simpler to decode, but usually mean a lower ECL, so the code can lose
+    /// some of its tolerance for damage.
+    pub fn prefer_fewer_blocks(mut self) -> Self {
+        self.prefer_fewer_blocks = true;
+        self
+    }
+
+    /// Appends a CRC-32 of the data to it, so the application reading the code can check it
+    /// arrived intact. Text gets the CRC of its UTF-8 bytes as 8 uppercase hex digits, which keeps
+    /// Alphanumeric mode available; binary data gets it as 4 big-endian bytes. Either way it takes
+    /// up capacity, and can push the code up a version.
+    pub fn append_crc32(mut self) -> Self {
+        self.append_crc32 = true;
+        self
+    }
+
+    /// Removes spaces and dashes from text before encoding it, so pasted card or phone numbers
+    /// like "4111 1111 1111 1111" can use the more compact Numeric mode. This changes the data
+    /// the code holds: scanning it gives back the text without them. Binary data is left alone.
+    pub fn strip_separators(mut self) -> Self {
+        self.strip_separators = true;
+        self
+    }
+
+    /// Makes sure a square in the middle of the code `fraction` of its width across, e.g. for a
+    /// logo, can be covered with the code still decoding, moving up to larger versions until
+    /// there are enough EC codewords to spare. A fifth of the width is a common logo size.
+    pub fn reserve_center(mut self, fraction: f32) -> Self {
+        self.reserve_center = Some(fraction);
+        self
+    }
+
+    /// Uses this version instead of the smallest one the data fits in, e.g. to print codes at a
+    /// fixed physical size. Building fails if the data doesn't fit in it.
+    pub fn version(mut self, version_num: u8) -> Self {
+        self.version = Some(version_num);
+        self
+    }
+
+    /// Masks the code with this mask, from 0 to 7, instead of picking one. The same as
+    /// `mask_selection(MaskSelection::Fixed(mask))`.
+    pub fn mask(self, mask: u8) -> Self {
+        self.mask_selection(MaskSelection::Fixed(mask))
+    }
+
+    /// Starts the data with an ECI header, telling readers which character set to decode it with,
+    /// e.g. 26 for UTF-8. It doesn't change how the data is encoded.
+    pub fn eci(mut self, designator: u32) -> Self {
+        self.eci = Some(designator);
+        self
+    }
+
+    /// Whether text that has to be written as UTF-8 in Bytes mode, like "日本語", gets an ECI
+    /// header saying so (designator 26), since readers otherwise assume ISO-8859-1. On by
+    /// default; the header costs 12 bits. An explicit `eci` takes precedence.
+    pub fn auto_eci(mut self, enabled: bool) -> Self {
+        self.auto_eci = enabled;
+        self
+    }
+
+    pub fn build(&self) -> Result<QRCode, Error> {
+        let start = Instant::now();
+        let stripped: String;
+        let data = match self.data {
+            Input::Text(text) if self.strip_separators => {
+                stripped = text.chars().filter(|c| *c != ' ' && *c != '-').collect();
+                Input::Text(&stripped)
+            }
+            data => data,
+        };
+        let with_crc;
+        let data = match data {
+            Input::Text(text) if self.append_crc32 => {
+                with_crc = format!("{}{:08X}", text, crc32(text.as_bytes())).into_bytes();
+                Input::Text(std::str::from_utf8(&with_crc).unwrap())
+            }
+            Input::Bytes(bytes) if self.append_crc32 => {
+                with_crc = [bytes, &crc32(bytes).to_be_bytes()].concat();
+                Input::Bytes(&with_crc)
+            }
+            data => data,
+        };
+        // reject hopelessly long input before the encoder allocates anything for it
+        let (length, limit) = match data {
+            Input::Text(text) => (text.len(), MAX_INPUT_BYTES),
+            Input::Bytes(bytes) => (bytes.len(), MAX_BYTES_MODE_BYTES),
+        };
+        if length > limit {
+            return Err(format!(
+                "The data is {} bytes long, more than any QR code can hold ({})",
+                length, limit
+            )
+            .into());
+        }
+        if let MaskSelection::Fixed(mask) = self.mask_selection {
+            if mask >= 8 {
+                return Err(format!("Invalid mask {}; masks run from 0 to 7", mask).into());
+            }
+        }
+        let mut encoder = self.encoder(data)?;
+        let version = match self.version {
+            Some(num) => fixed_version(&encoder, num, &self.ecl)?,
+            None => choose_version(&encoder, &self.ecl)?,
+        };
+        let ecl = if self.prefer_fewer_blocks {
+            use ErrorCorrectionLevel::*;
+            *[High, Quartile, Medium, Low]
+                .iter()
+                .filter(|ecl| fits(&encoder, version, ecl))
+                .min_by_key(|ecl| version.values_at_ecl(ecl).total_blocks())
+                .unwrap_or(&self.ecl)
+        } else {
+            self.ecl
+        };
+        let version = match self.reserve_center {
+            Some(fraction) => {
+                let roomy = reserve_center(version, ecl, fraction)?;
+                if self.version.is_some() && roomy.num != version.num {
+                    return Err(format!(
+                        "Version {} can't spare enough EC codewords to cover {} of its width",
+                        version.num, fraction
+                    )
+                    .into());
+                }
+                roomy
+            }
+            None => version,
+        };
+        build_code(&mut encoder, version, ecl, self.mask_selection, start)
+    }
+
+    /// Encodes the data with the builder's character set and ECI header, if any.
+    fn encoder(&self, data: Input) -> Result<QRBitstreamEncoder, Error> {
+        let encoder = match data {
+            Input::Text(text) => QRBitstreamEncoder::with_charset(text, self.byte_charset)?,
+            Input::Bytes(bytes) => QRBitstreamEncoder::from_bytes(bytes),
+        };
+        let designator = match data {
+            Input::Text(text) if self.auto_eci && needs_utf8_eci(text, self.byte_charset) => {
+                Some(UTF8_ECI)
+            }
+            _ => None,
+        };
+        match self.eci.or(designator) {
+            Some(designator) => encoder.with_eci(designator),
+            None => Ok(encoder),
+        }
+    }
+}
+
+/// Everything that decides how a code comes out, so a service can store these instead of the
+/// image and get the same code back from `regenerate`. With the `serde` feature, they can be
+/// serialized.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GenerationParams {
+    pub data: String,
+    pub ecl: ErrorCorrectionLevel,
+    /// The version to use, or `None` for the smallest the data fits in.
+    pub version: Option<u8>,
+    /// The mask to use, or `None` to pick the lowest-penalty one.
+    pub mask: Option<u8>,
+    /// The ECI designator to start the data with, if any.
+    pub eci: Option<u32>,
+    pub byte_charset: ByteCharset,
+}
+
+impl GenerationParams {
+    /// Parameters that generate the text like `create_qr_code` does.
+    pub fn new(data: &str, ecl: ErrorCorrectionLevel) -> Self {
+        GenerationParams {
+            data: data.to_owned(),
+            ecl,
+            version: None,
+            mask: None,
+            eci: None,
+            byte_charset: ByteCharset::Auto,
+        }
+    }
+}
+
+/// Generates the code the parameters describe. The same parameters always give the same code.
+pub fn regenerate(params: &GenerationParams) -> Result<QRCode, Error> {
+    let mut builder = QRCodeBuilder::new(&params.data)
+        .ecl(params.ecl)
+        .byte_charset(params.byte_charset);
+    if let Some(version_num) = params.version {
+        builder = builder.version(version_num);
+    }
+    if let Some(mask) = params.mask {
+        builder = builder.mask(mask);
+    }
+    if let Some(designator) = params.eci {
+        builder = builder.eci(designator);
+    }
+    builder.build()
+}
+
+/// The version with this number, if the data fits in it at the ECL.
+fn fixed_version(
+    encoder: &QRBitstreamEncoder,
+    version_num: u8,
+    ecl: &ErrorCorrectionLevel,
+) -> Result<&'static Version, Error> {
+    if !(1..=40).contains(&version_num) {
+        return Err(format!(
+            "There's no version {}; versions run from 1 to 40",
+            version_num
+        )
+        .into());
+    }
+    let version = Version::by_num(version_num as usize);
+    if !fits(encoder, version, ecl) {
+        return Err(format!(
+            "The data is too long for a version {} code at that error correction level",
+            version_num
+        )
+        .into());
+    }
+    Ok(version)
+}
+
+/// The first version from `smallest` on whose EC codewords can cover a square in the middle
+/// `fraction` of the code's width across.
+fn reserve_center(
+    smallest: &'static Version,
+    ecl: ErrorCorrectionLevel,
+    fraction: f32,
+) -> Result<&'static Version, Error> {
+    if !(0.0..1.0).contains(&fraction) {
+        return Err(format!("Can't reserve {} of the code's width", fraction).into());
+    }
+    (smallest.num as usize..=40)
+        .map(Version::by_num)
+        .find(|version| {
+            let width = (version.modules_per_side() as f32 * fraction).ceil() as usize;
+            width * width <= obscurable_modules(version.values_at_ecl(&ecl))
+        })
+        .ok_or_else(|| {
+            format!(
+                "No version at {:?} can spare enough EC codewords to cover {} of its width",
+                ecl, fraction
+            )
+            .into()
+        })
+}
+
+/// Lays out the encoded data in a code of the version, marks its padding, records its stats and
+/// masks it. The elapsed time is counted from `start`.
+fn build_code(
+    encoder: &mut QRBitstreamEncoder,
+    version: &'static Version,
+    ecl: ErrorCorrectionLevel,
+    mask_selection: MaskSelection,
+    start: Instant,
+) -> Result<QRCode, Error> {
     let data_codewords = encoder.codewords(version, &ecl)?;
-    let data_with_ec = bitstream_with_ec(data_codewords, version_ecl_data);
-    Ok(QRCode::new(version, data_with_ec))
+    let data_with_ec = bitstream_with_ec(data_codewords, version.values_at_ecl(&ecl))?;
+    let mut code = layout_code(encoder, version, ecl, data_with_ec);
+    code.set_mask(code.select_mask(mask_selection));
+    // masking is part of generating the code, so time it too
+    if let Some(stats) = &mut code.stats {
+        stats.elapsed = start.elapsed();
+    }
+    Ok(code)
+}
+
+/// The stage of generation after error correction: places the data and EC codewords from
+/// `bitstream_with_ec` in a code of the version, marks the encoder's padding and records the
+/// generation stats. The code still has to be masked with `QRCode::set_mask`, e.g. with the
+/// mask from `QRCode::select_mask`. Together with the encoder, `choose_version` and
+/// `bitstream_with_ec`, this lets each stage of `create_qr_code` be run and timed on its own.
+pub fn layout_code(
+    encoder: &QRBitstreamEncoder,
+    version: &'static Version,
+    ecl: ErrorCorrectionLevel,
+    data_with_ec: QREncodedData,
+) -> QRCode {
+    let start = Instant::now();
+    let version_ecl_data = version.values_at_ecl(&ecl);
+    let mut code = QRCode::unmasked(version, ecl, data_with_ec);
+
+    let padding_codewords = encoder.padding_codeword_count(version, &ecl);
+    let codewords_before_padding = version_ecl_data.data_codewords - padding_codewords;
+    let padding_positions: Vec<usize> = interleaved_data_order(version_ecl_data)
+        .iter()
+        .enumerate()
+        .filter(|(_, &idx)| idx >= codewords_before_padding)
+        .map(|(position, _)| position)
+        .collect();
+    code.mark_padding_codewords(&padding_positions);
+
+    code.stats = Some(GenerationStats {
+        ecl,
+        encoding: encoder.encoding(),
+        character_count: encoder.character_count(),
+        codewords_before_padding,
+        padding_codewords,
+        ec_codewords: version_ecl_data.total_ec_codewords(),
+        elapsed: start.elapsed(),
+    });
+    code
 }
 
 #[cfg(test)]
-pub(self) fn _read_fixture<T: serde::de::DeserializeOwned>(path: &str) -> T {
+fn _read_fixture<T: serde::de::DeserializeOwned>(path: &str) -> T {
     let mut fixture_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     fixture_file.push(path);
     serde_yaml::from_reader(std::fs::File::open(fixture_file).unwrap()).unwrap()
 }
+
+/// Compares the code module by module against a text grid of `#` (black) and `.` (white), one
+/// line per row, and panics with the first mismatching coordinate.
+#[cfg(test)]
+fn assert_matrix_matches(code: &QRCode, path: &str) {
+    let mut fixture_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    fixture_file.push(path);
+    let text = std::fs::read_to_string(fixture_file).unwrap();
+    let grid: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+    let side = code.version.modules_per_side();
+    assert_eq!(grid.len(), side, "{} has the wrong number of rows", path);
+    for (y, line) in grid.iter().enumerate() {
+        assert_eq!(
+            line.len(),
+            side,
+            "row {} of {} has the wrong length",
+            y,
+            path
+        );
+        for (x, expected) in line.chars().enumerate() {
+            let expected = match expected {
+                '#' => true,
+                '.' => false,
+                other => panic!("unexpected {:?} at ({}, {}) in {}", other, x, y, path),
+            };
+            assert_eq!(
+                code.rows[x][y].black(),
+                expected,
+                "first mismatch at ({}, {}) against {}",
+                x,
+                y,
+                path
+            );
+        }
+    }
+}
+
+/// Formats the code as the text grid `assert_matrix_matches` reads, for writing fixtures.
+#[cfg(test)]
+fn matrix_fixture_text(code: &QRCode) -> String {
+    let side = code.version.modules_per_side();
+    let mut text = String::with_capacity(side * (side + 1));
+    for y in 0..side {
+        for x in 0..side {
+            text.push(if code.rows[x][y].black() { '#' } else { '.' });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Generates a numeric code at each of the 40 versions, in order. Each holds one digit more than
+/// the previous version can, with the rest of its capacity filled with padding codewords.
+#[cfg(test)]
+fn generate_all_versions(ecl: ErrorCorrectionLevel) -> Vec<QRCode> {
+    use crate::qr::encode::QREncoding;
+    use crate::qr::version::Version;
+
+    (1..=40)
+        .map(|num| {
+            let digits = match num {
+                1 => 1,
+                _ => Version::by_num(num - 1).character_capacity(&ecl, QREncoding::Numeric) + 1,
+            };
+            let data: String = "0123456789".chars().cycle().take(digits).collect();
+            QRCodeBuilder::new(&data).ecl(ecl).build().unwrap()
+        })
+        .collect()
+}
+
+/// Versions with checked-in snapshots in fixtures/versions, generated at Medium.
+#[cfg(test)]
+const SNAPSHOT_VERSIONS: [usize; 3] = [1, 10, 27];
+
+#[cfg(test)]
+fn version_fixture_path(num: usize) -> String {
+    format!("fixtures/versions/v{:02}_m.txt", num)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::decode::decode_matrix;
+    use crate::qr::encode::QREncoding;
+    use image::{GenericImageView, ImageFormat};
+
+    #[test]
+    fn test_builder_byte_charset() {
+        for (charset, expected) in &[
+            (ByteCharset::Auto, vec![b'c', b'a', b'f', 0xE9]),
+            (ByteCharset::Latin1, vec![b'c', b'a', b'f', 0xE9]),
+            (ByteCharset::Utf8, "café".as_bytes().to_vec()),
+        ] {
+            let code = QRCodeBuilder::new("café")
+                .byte_charset(*charset)
+                .build()
+                .unwrap();
+            assert_eq!(
+                &decode_matrix(&code.to_matrix(), code.version).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_builder_from_bytes() {
+        let data = [0x00, 0x7F, 0x80, 0xFF];
+        let code = QRCodeBuilder::from_bytes(&data).build().unwrap();
+        assert_eq!(code.stats.as_ref().unwrap().encoding, QREncoding::Bytes);
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_prefer_fewer_blocks() {
+        // version 3 splits Quartile into 2 blocks, but Medium and Low into 1
+        let data = "A".repeat(40);
+        let builder = QRCodeBuilder::new(&data).ecl(ErrorCorrectionLevel::Quartile);
+        let code = builder.build().unwrap();
+        assert_eq!(code.version.num, 3);
+        let blocks = |code: &QRCode| {
+            let ecl = code.stats.as_ref().unwrap().ecl;
+            code.version.values_at_ecl(&ecl).total_blocks()
+        };
+        assert_eq!(blocks(&code), 2);
+
+        let fewer = builder.prefer_fewer_blocks().build().unwrap();
+        assert_eq!(fewer.version.num, 3);
+        assert_eq!(
+            fewer.stats.as_ref().unwrap().ecl,
+            ErrorCorrectionLevel::Medium
+        );
+        assert_eq!(blocks(&fewer), 1);
+        assert_eq!(
+            decode_matrix(&fewer.to_matrix(), fewer.version).unwrap(),
+            data.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_qr_code_framed() {
+        let payload: Vec<u8> = (0..300).map(|i| (i % 256) as u8).collect();
+        let code = create_qr_code_framed(&payload, ErrorCorrectionLevel::Low).unwrap();
+        let decoded = decode_matrix(&code.to_matrix(), code.version).unwrap();
+        assert_eq!(decoded[..2], [0x01, 0x2C]);
+        assert_eq!(decoded[2..], payload[..]);
+        assert!(create_qr_code_framed(&[0; 2952], ErrorCorrectionLevel::Low).is_err());
+    }
+
+    #[test]
+    fn test_append_crc32() {
+        let code = QRCodeBuilder::new("HELLO WORLD")
+            .append_crc32()
+            .build()
+            .unwrap();
+        // the standard CRC-32 check value is for "123456789"
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let expected = format!("HELLO WORLD{:08X}", crc32(b"HELLO WORLD"));
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            expected.as_bytes()
+        );
+        assert_eq!(code.payload_mode(), Some(QREncoding::Alphanumeric));
+
+        let data = [0x00, 0xFF];
+        let code = QRCodeBuilder::from_bytes(&data)
+            .append_crc32()
+            .build()
+            .unwrap();
+        let decoded = decode_matrix(&code.to_matrix(), code.version).unwrap();
+        assert_eq!(decoded[..2], data);
+        assert_eq!(decoded[2..], crc32(&data).to_be_bytes());
+    }
+
+    #[test]
+    fn test_structured_append_balanced() {
+        let data = "the quick brown fox jumps over a lazy dog";
+        let codes = create_structured_append(data, 1, ErrorCorrectionLevel::Low).unwrap();
+        assert_eq!(codes.len(), 3);
+        let parts: Vec<Vec<u8>> = codes
+            .iter()
+            .map(|code| decode_matrix(&code.to_matrix(), code.version).unwrap())
+            .collect();
+        // 41 characters over 3 symbols, where each holds up to 15 bytes
+        let lengths: Vec<usize> = parts.iter().map(Vec::len).collect();
+        assert_eq!(lengths, vec![14, 14, 13]);
+        assert_eq!(parts.concat(), data.as_bytes());
+        assert!(codes.iter().all(|code| code.version.num == 1));
+
+        let single = create_structured_append("HELLO", 1, ErrorCorrectionLevel::Low).unwrap();
+        assert_eq!(single.len(), 1);
+        assert!(create_structured_append(&"x".repeat(1000), 1, ErrorCorrectionLevel::Low).is_err());
+    }
+
+    #[test]
+    fn test_staged_generation() {
+        for &ecl in &[ErrorCorrectionLevel::Low, ErrorCorrectionLevel::High] {
+            let data = "https://example.com/staged";
+            let mut encoder = QRBitstreamEncoder::new(data);
+            let version = choose_version(&encoder, &ecl).unwrap();
+            let codewords = encoder.codewords(version, &ecl).unwrap();
+            let data_with_ec = bitstream_with_ec(codewords, version.values_at_ecl(&ecl)).unwrap();
+            let mut code = layout_code(&encoder, version, ecl, data_with_ec);
+            code.set_mask(code.select_mask(MaskSelection::Full));
+
+            let expected = create_qr_code(data, ecl).unwrap();
+            assert_eq!(code.mask, expected.mask);
+            assert_eq!(code.to_matrix(), expected.to_matrix());
+        }
+    }
+
+    #[test]
+    fn test_strip_separators() {
+        let card = "4111 1111 1111 1111";
+        // spaces are in the Alphanumeric character set, but not the Numeric one
+        let plain = QRCodeBuilder::new(card).build().unwrap();
+        assert_eq!(plain.payload_mode(), Some(QREncoding::Alphanumeric));
+        let code = QRCodeBuilder::new(card).strip_separators().build().unwrap();
+        assert_eq!(code.payload_mode(), Some(QREncoding::Numeric));
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            b"4111111111111111"
+        );
+        let phone = QRCodeBuilder::new("555-010-9999")
+            .strip_separators()
+            .build()
+            .unwrap();
+        assert_eq!(phone.payload_length(), 10);
+    }
+
+    #[test]
+    fn test_single_character() {
+        use ErrorCorrectionLevel::*;
+        // é goes into Bytes mode as the one ISO-8859-1 byte 0xE9
+        for (data, mode, bytes) in &[
+            ("1", QREncoding::Numeric, vec![b'1']),
+            ("A", QREncoding::Alphanumeric, vec![b'A']),
+            ("é", QREncoding::Bytes, vec![0xE9]),
+        ] {
+            for &ecl in &[Low, Medium, Quartile, High] {
+                let code = create_qr_code(data, ecl).unwrap();
+                assert_eq!(code.version.num, 1);
+                assert_eq!(code.payload_mode(), Some(*mode));
+                assert_eq!(code.payload_length(), 1);
+                assert_eq!(
+                    &decode_matrix(&code.to_matrix(), code.version).unwrap(),
+                    bytes
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reserve_center() {
+        let builder = QRCodeBuilder::new("HELLO WORLD");
+        assert_eq!(builder.build().unwrap().version.num, 1);
+        let code = builder.reserve_center(0.25).build().unwrap();
+        // version 5 at Medium could cover 96 modules of the 10 by 10 square it needs, but version 6
+        // can cover 128 of its 11 by 11
+        assert_eq!(code.version.num, 6);
+        assert!(code.max_obscurable_modules() >= 121);
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            b"HELLO WORLD"
+        );
+        assert!(QRCodeBuilder::new("HELLO WORLD")
+            .reserve_center(0.9)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_too_long_input() {
+        let huge = "1".repeat(10 * 1024 * 1024);
+        let error = create_qr_code(&huge, ErrorCorrectionLevel::Low)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            "The data is 10485760 bytes long, more than any QR code can hold (7089)"
+        );
+        // the invalid mask and the text's lack of an ISO-8859-1 form would each fail the build
+        // later on, so this error shows the length is checked before anything else runs
+        let error = QRCodeBuilder::new(&"日".repeat(1024 * 1024))
+            .byte_charset(ByteCharset::Latin1)
+            .mask(8)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            "The data is 3145728 bytes long, more than any QR code can hold (7089)"
+        );
+
+        let bytes = vec![0u8; MAX_BYTES_MODE_BYTES + 1];
+        assert!(QRCodeBuilder::from_bytes(&bytes).build().is_err());
+        // input right at the limit still gets through
+        let long = "1".repeat(MAX_INPUT_BYTES);
+        assert_eq!(
+            QRCodeBuilder::new(&long)
+                .ecl(ErrorCorrectionLevel::Low)
+                .build()
+                .unwrap()
+                .version
+                .num,
+            40
+        );
+    }
+
+    #[test]
+    fn test_forced_version_mask_and_eci() {
+        let code = QRCodeBuilder::new("HELLO WORLD")
+            .version(5)
+            .mask(3)
+            .eci(26)
+            .build()
+            .unwrap();
+        assert_eq!(code.version.num, 5);
+        assert_eq!(code.mask, Some(3));
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            b"HELLO WORLD"
+        );
+
+        for builder in &[
+            QRCodeBuilder::new("HELLO WORLD").version(0),
+            QRCodeBuilder::new("HELLO WORLD").version(41),
+            QRCodeBuilder::new(&"HELLO WORLD".repeat(3)).version(1),
+            QRCodeBuilder::new("HELLO WORLD").mask(8),
+            QRCodeBuilder::new("HELLO WORLD").eci(1_000_000),
+        ] {
+            assert!(builder.build().is_err());
+        }
+    }
+
+    fn regenerate_params() -> GenerationParams {
+        GenerationParams {
+            version: Some(4),
+            mask: Some(6),
+            eci: Some(26),
+            byte_charset: ByteCharset::Utf8,
+            ..GenerationParams::new("Grüße aus Köln", ErrorCorrectionLevel::Quartile)
+        }
+    }
+
+    #[test]
+    fn test_regenerate() {
+        let params = regenerate_params();
+        let code = regenerate(&params).unwrap();
+        assert_eq!(code.version.num, 4);
+        assert_eq!(code.mask, Some(6));
+        assert_eq!(regenerate(&params).unwrap().to_matrix(), code.to_matrix());
+        assert_eq!(
+            regenerate(&GenerationParams::new(
+                "HELLO",
+                ErrorCorrectionLevel::Medium
+            ))
+            .unwrap()
+            .to_matrix(),
+            create_qr_code("HELLO", ErrorCorrectionLevel::Medium)
+                .unwrap()
+                .to_matrix()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_regenerate_from_json() {
+        let params = regenerate_params();
+        let json = serde_json::to_string(&params).unwrap();
+        let restored: GenerationParams = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, params);
+        assert_eq!(
+            regenerate(&restored).unwrap().to_matrix(),
+            regenerate(&params).unwrap().to_matrix()
+        );
+    }
+
+    #[test]
+    fn test_mixed_segments_round_trip() {
+        use crate::qr::encode::{QREncoding, Segment};
+
+        let mut encoder = QRBitstreamEncoder::from_segments(vec![
+            Segment {
+                mode: QREncoding::Alphanumeric,
+                data: "HELLO".to_owned(),
+            },
+            Segment {
+                mode: QREncoding::Numeric,
+                data: "123".to_owned(),
+            },
+            Segment {
+                mode: QREncoding::Bytes,
+                data: "world".to_owned(),
+            },
+        ])
+        .unwrap();
+        let ecl = ErrorCorrectionLevel::Medium;
+        let version = choose_version(&encoder, &ecl).unwrap();
+        let code = build_code(
+            &mut encoder,
+            version,
+            ecl,
+            MaskSelection::Full,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            b"HELLO123world"
+        );
+        assert_eq!(code.payload_length(), 13);
+        assert_eq!(code.payload_mode(), Some(QREncoding::Alphanumeric));
+    }
+
+    #[test]
+    fn test_create_qr_code_bytes() {
+        let data: Vec<u8> = (0..=255).collect();
+        let code = create_qr_code_bytes(&data, ErrorCorrectionLevel::Medium).unwrap();
+        assert_eq!(code.payload_length(), 256);
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_create_qr_code_with_version() {
+        let code =
+            create_qr_code_with_version("HELLO WORLD", ErrorCorrectionLevel::High, 10).unwrap();
+        assert_eq!(code.version.num, 10);
+        assert_eq!(code.rows.len(), 57);
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            b"HELLO WORLD"
+        );
+
+        for num in [0, 41] {
+            let err = create_qr_code_with_version("HELLO WORLD", ErrorCorrectionLevel::Low, num)
+                .err()
+                .unwrap();
+            assert!(err.contains("versions run from 1 to 40"));
+        }
+        let err = create_qr_code_with_version(&"A".repeat(26), ErrorCorrectionLevel::Low, 1)
+            .err()
+            .unwrap();
+        assert!(err.contains("too long"));
+    }
+
+    #[test]
+    fn test_supported_symbologies() {
+        assert_eq!(supported_symbologies(), ["QR"]);
+    }
+
+    #[test]
+    fn test_control_characters() {
+        // GS1 DataMatrix style group separators aren't special here; they're encoded as bytes
+        let data = "\u{1d}0104012345012345\u{1d}10ABC";
+        let code = create_qr_code(data, ErrorCorrectionLevel::Medium).unwrap();
+        assert_eq!(code.payload_mode(), Some(QREncoding::Bytes));
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            data.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_auto_eci() {
+        let codewords = |builder: QRCodeBuilder| {
+            builder
+                .build()
+                .unwrap()
+                .stats
+                .unwrap()
+                .codewords_before_padding
+        };
+        // 4 + 8 + 9 * 8 bits of UTF-8 and the terminator, plus 12 for the ECI header
+        assert_eq!(codewords(QRCodeBuilder::new("日本語")), 13);
+        assert_eq!(codewords(QRCodeBuilder::new("日本語").auto_eci(false)), 11);
+        assert_eq!(codewords(QRCodeBuilder::new("hello")), 7);
+        assert_eq!(codewords(QRCodeBuilder::new("hello").auto_eci(false)), 7);
+
+        let code = QRCodeBuilder::new("日本語").build().unwrap();
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            "日本語".as_bytes()
+        );
+
+        let leading_bits = |builder: QRCodeBuilder, count| -> String {
+            builder
+                .encoder(builder.data)
+                .unwrap()
+                .bitstream(Version::by_num(1), &ErrorCorrectionLevel::Low)
+                .unwrap()
+                .iter()
+                .take(count)
+                .map(|bit| if *bit { '1' } else { '0' })
+                .collect()
+        };
+        // the ECI mode indicator and designator 26, then the Bytes mode header for 9 bytes
+        assert_eq!(
+            leading_bits(QRCodeBuilder::new("日本語"), 24),
+            "0111".to_owned() + "00011010" + "0100" + "00001001"
+        );
+        // no ECI header at all, just the Bytes mode header for 5 bytes
+        assert_eq!(
+            leading_bits(QRCodeBuilder::new("hello"), 12),
+            "0100".to_owned() + "00000101"
+        );
+    }
+
+    #[test]
+    fn test_mask_selection() {
+        for selection in &[
+            MaskSelection::Full,
+            MaskSelection::Fast,
+            MaskSelection::MaxEntropy,
+        ] {
+            let code = QRCodeBuilder::new("https://example.com/mask")
+                .mask_selection(*selection)
+                .build()
+                .unwrap();
+            assert_eq!(code.mask, Some(code.select_mask(*selection)));
+            assert_eq!(
+                decode_matrix(&code.to_matrix(), code.version).unwrap(),
+                b"https://example.com/mask"
+            );
+        }
+    }
+
+    #[test]
+    fn test_payload_length() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        assert_eq!(code.payload_length(), 11);
+        assert_eq!(code.payload_mode(), Some(QREncoding::Alphanumeric));
+        let bytes = create_qr_code("café", ErrorCorrectionLevel::Medium).unwrap();
+        assert_eq!(bytes.payload_length(), 4);
+        assert_eq!(bytes.payload_mode(), Some(QREncoding::Bytes));
+    }
+
+    #[test]
+    fn test_generation_stats() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let stats = code.stats.unwrap();
+        let capacity = code.version.codeword_count(&ErrorCorrectionLevel::Medium);
+        assert_eq!(stats.encoding, QREncoding::Alphanumeric);
+        // mode (4) + count (9) + data (61) bits
+        assert_eq!(stats.codewords_before_padding, 10);
+        assert_eq!(capacity, 16);
+        assert_eq!(
+            stats.padding_codewords,
+            capacity - stats.codewords_before_padding
+        );
+        assert_eq!(stats.ec_codewords, 10);
+    }
+
+    #[test]
+    fn test_max_obscurable_modules() {
+        let low = create_qr_code("HELLO", ErrorCorrectionLevel::Low).unwrap();
+        let high = create_qr_code("HELLO", ErrorCorrectionLevel::High).unwrap();
+        assert_eq!((low.version.num, high.version.num), (1, 1));
+        // version 1 has 7 EC codewords at Low and 17 at High
+        assert_eq!(low.max_obscurable_modules(), 3 * 4);
+        assert_eq!(high.max_obscurable_modules(), 8 * 4);
+
+        let without_stats = QRCode::new(low.version, ErrorCorrectionLevel::Low, Default::default());
+        assert_eq!(without_stats.max_obscurable_modules(), 0);
+    }
+
+    #[test]
+    fn test_padding_modules() {
+        // 3 codewords of data and terminator, then 16 of padding in a single block
+        let code = create_qr_code("1", ErrorCorrectionLevel::Low).unwrap();
+        assert_eq!(code.stats.as_ref().unwrap().padding_codewords, 16);
+        let coords_order = code.zig_zag_scanner();
+        for (position, coords) in coords_order.iter().enumerate() {
+            assert_eq!(code.is_padding(*coords), (24..152).contains(&position));
+        }
+        assert_eq!(code.padding.iter().flatten().filter(|&&p| p).count(), 128);
+    }
+
+    #[test]
+    fn test_padding_modules_interleaved() {
+        let ecl = ErrorCorrectionLevel::High;
+        let mut code = create_qr_code(&"1".repeat(100), ecl).unwrap();
+        // the padding values are only visible without the mask
+        code.apply_mask(code.mask.unwrap());
+        let ecl_data = code.version.values_at_ecl(&ecl);
+        assert!(ecl_data.group1.blocks > 1);
+        let stats = code.stats.as_ref().unwrap();
+        assert!(stats.padding_codewords > 0);
+
+        let mut padding_codewords = 0;
+        let coords_order = code.zig_zag_scanner();
+        for codeword in coords_order.chunks(8).take(ecl_data.data_codewords) {
+            let padding = code.is_padding(codeword[0]);
+            assert!(codeword
+                .iter()
+                .all(|&coords| code.is_padding(coords) == padding));
+            if padding {
+                let value = codeword.iter().fold(0u8, |acc, &coords| {
+                    acc << 1 | code.module(coords).black() as u8
+                });
+                assert!(value == 0xEC || value == 0x11);
+                padding_codewords += 1;
+            }
+        }
+        assert_eq!(padding_codewords, stats.padding_codewords);
+    }
+
+    #[test]
+    fn test_base64_png() {
+        let encoded = create_qr_base64_png("HELLO WORLD", ErrorCorrectionLevel::Medium, 3).unwrap();
+        assert!(!encoded.starts_with("data:"));
+        let png = base64::decode(&encoded).unwrap();
+        let img = image::load_from_memory_with_format(&png, ImageFormat::Png).unwrap();
+        // 21 modules plus a quiet zone of 4 on each side
+        assert_eq!(img.dimensions(), (29 * 3, 29 * 3));
+    }
+
+    #[test]
+    fn test_themed_qr() {
+        let theme = Theme::ocean();
+        let img = create_themed_qr("HELLO WORLD", ErrorCorrectionLevel::Medium, &theme).unwrap();
+        // inside the top left finder's outer ring, past the 4 module quiet zone
+        assert_eq!(*img.get_pixel(16 + 2, 16 + 2), theme.finder);
+        assert_eq!(*img.get_pixel(0, 0), theme.background);
+    }
+
+    #[test]
+    fn test_qr_full() {
+        let options = RenderOptions::square(5);
+        let (code, img) =
+            create_qr_full("HELLO WORLD", ErrorCorrectionLevel::Medium, &options).unwrap();
+        let side = (code.version.modules_per_side() as u32 + 2 * options.quiet_zone) * 5;
+        assert_eq!(img.dimensions(), (side, side));
+    }
+
+    #[test]
+    fn test_event_qr() {
+        let start = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_604_169_000);
+        let end = start + std::time::Duration::from_secs(3600);
+        let code = create_event_qr("Launch", start, end, None, ErrorCorrectionLevel::Low).unwrap();
+        let text =
+            String::from_utf8(decode_matrix(&code.to_matrix(), code.version).unwrap()).unwrap();
+        assert!(text.starts_with("BEGIN:VEVENT\r\n"));
+        assert!(text.contains("DTSTART:20201031T183000Z\r\n"));
+        assert!(text.contains("DTEND:20201031T193000Z\r\n"));
+    }
+
+    #[test]
+    fn test_geo_qr() {
+        let code = create_geo_qr(48.858844, 2.294351, ErrorCorrectionLevel::Medium).unwrap();
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            b"geo:48.858844,2.294351"
+        );
+        assert!(create_geo_qr(-90.01, 0.0, ErrorCorrectionLevel::Medium).is_err());
+    }
+
+    #[test]
+    fn test_mailto_and_tel_qr() {
+        let ecl = ErrorCorrectionLevel::Medium;
+        let decoded = |code: QRCode| decode_matrix(&code.to_matrix(), code.version).unwrap();
+        let mailto = create_mailto_qr("me@example.com", Some("Q & A"), None, ecl).unwrap();
+        assert_eq!(
+            decoded(mailto),
+            b"mailto:me@example.com?subject=Q%20%26%20A"
+        );
+        assert_eq!(
+            decoded(create_tel_qr("+44 20 7946 0000", ecl).unwrap()),
+            b"tel:+442079460000"
+        );
+    }
+
+    /// The worked example from Annex I of ISO/IEC 18004: "01234567" at 1-M.
+    #[test]
+    fn test_iso_example_01234567() {
+        use crate::qr::version::Version;
+
+        let version = Version::by_num(1);
+        let ecl = ErrorCorrectionLevel::Medium;
+        let mut encoder = QRBitstreamEncoder::new("01234567");
+        let data_codewords = encoder.codewords(version, &ecl).unwrap();
+        assert_eq!(
+            data_codewords,
+            vec![
+                0x10, 0x20, 0x0C, 0x56, 0x61, 0x80, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11,
+                0xEC, 0x11
+            ]
+        );
+        let with_ec: Vec<u8> = bitstream_with_ec(data_codewords, version.values_at_ecl(&ecl))
+            .unwrap()
+            .into_vec()
+            .into_iter()
+            .map(u8::reverse_bits)
+            .collect();
+        assert_eq!(
+            with_ec[16..],
+            [0xA5, 0x24, 0xD4, 0xC1, 0xED, 0x36, 0xC7, 0x87, 0x2C, 0x55]
+        );
+
+        // the Annex lays the codewords out with mask 010, so the format information reads
+        // 101111001111100 (Annex C)
+        let bitstream = bitstream_with_ec(
+            QRBitstreamEncoder::new("01234567")
+                .codewords(version, &ecl)
+                .unwrap(),
+            version.values_at_ecl(&ecl),
+        )
+        .unwrap();
+        let code = QRCode::with_mask(version, ecl, bitstream, 2).unwrap();
+        assert_matrix_matches(&code, "fixtures/iso_01234567_1m.txt");
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), code.version).unwrap(),
+            b"01234567"
+        );
+    }
+
+    #[test]
+    fn test_hello_world_matrix() {
+        // masked with mask 0, but without version information (not needed at version 1)
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        assert_matrix_matches(&code, "fixtures/hello_world_1m.txt");
+    }
+
+    #[test]
+    fn test_generate_all_versions() {
+        let codes = generate_all_versions(ErrorCorrectionLevel::Medium);
+        for (i, code) in codes.iter().enumerate() {
+            assert_eq!(code.version.num as usize, i + 1);
+        }
+        for code in &[&codes[0], &codes[6], &codes[39]] {
+            let decoded = decode_matrix(&code.to_matrix(), code.version).unwrap();
+            assert!(decoded.starts_with(b"0123456789") || decoded == b"0");
+        }
+    }
+
+    #[test]
+    fn test_version_snapshots() {
+        let codes = generate_all_versions(ErrorCorrectionLevel::Medium);
+        for &num in SNAPSHOT_VERSIONS.iter() {
+            assert_matrix_matches(&codes[num - 1], &version_fixture_path(num));
+        }
+    }
+
+    /// Rewrites the version snapshots from the current generator; run with `--ignored` after an
+    /// intentional change to the output, and review the diff.
+    #[test]
+    #[ignore]
+    fn write_version_snapshots() {
+        let codes = generate_all_versions(ErrorCorrectionLevel::Medium);
+        let mut dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        dir.push("fixtures/versions");
+        std::fs::create_dir_all(&dir).unwrap();
+        for &num in SNAPSHOT_VERSIONS.iter() {
+            let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push(version_fixture_path(num));
+            std::fs::write(path, matrix_fixture_text(&codes[num - 1])).unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "first mismatch at (0, 0)")]
+    fn test_matrix_mismatch() {
+        let mut code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        code.rows[0][0] = qr::pattern::Module::Finder(false);
+        assert_matrix_matches(&code, "fixtures/hello_world_1m.txt");
+    }
+}
@@ -0,0 +1,761 @@
+//! A minimal decoder for clean, noise-free module matrices. It doesn't do any image detection;
+//! it's mostly here so the encoder's output can be checked against itself.
+
+use encoding::all::ISO_8859_1;
+use encoding::{DecoderTrap, Encoding};
+use image::imageops::grayscale;
+use image::{GrayImage, RgbImage};
+
+use crate::qr::encode::QREncoding;
+use crate::qr::error_correction::{correct_errors, ErrorCorrectionLevel};
+use crate::qr::pattern::{
+    format_information, format_information_coordinates, mask_applies, Coordinates, QRCode,
+};
+use crate::qr::version::{Version, VersionEclData};
+use crate::qr::{BitMatrix, Error};
+
+use ErrorCorrectionLevel::*;
+
+const ALPHANUMERIC_CHARS: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Reads both copies of the format information.
+fn read_format_copies(matrix: &BitMatrix) -> (u16, u16) {
+    let [first_copy, second_copy] = format_information_coordinates(matrix.len());
+    let read = |coords: &[Coordinates]| {
+        coords
+            .iter()
+            .rev()
+            .fold(0u16, |acc, &(x, y)| acc << 1 | matrix[x][y] as u16)
+    };
+    (read(&first_copy), read(&second_copy))
+}
+
+/// Finds the ECL and mask whose format string is closest to what was read, if any are close
+/// enough to be corrected (the BCH code can fix up to 3 bit errors).
+fn read_format_information(matrix: &BitMatrix) -> Option<(ErrorCorrectionLevel, u8)> {
+    let (first_copy, second_copy) = read_format_copies(matrix);
+    let mut best = None;
+    let mut best_distance = 4;
+    for ecl in &[Low, Medium, Quartile, High] {
+        for mask in 0..8 {
+            let bits = format_information(ecl, mask);
+            for copy in &[first_copy, second_copy] {
+                let distance = (bits ^ copy).count_ones();
+                if distance < best_distance {
+                    best_distance = distance;
+                    best = Some((*ecl, mask));
+                }
+            }
+        }
+    }
+    best
+}
+
+/// Reads the interleaved codewords out of the data region, in zig-zag order.
+fn read_codewords(
+    matrix: &BitMatrix,
+    template: &QRCode,
+    mask: u8,
+    count: usize,
+) -> Result<Vec<u8>, Error> {
+    let coords = template.zig_zag_scanner();
+    if coords.len() < count * 8 {
+        return Err("The data region is too small for the version's codewords".into());
+    }
+    Ok(coords
+        .chunks(8)
+        .take(count)
+        .map(|byte| {
+            byte.iter().fold(0u8, |acc, &(x, y)| {
+                acc << 1 | (matrix[x][y] ^ mask_applies(mask, (x, y))) as u8
+            })
+        })
+        .collect())
+}
+
+/// Splits the interleaved codewords back into blocks, corrects any errors in each one, and
+/// returns just the data codewords in their original order.
+fn deinterleave(codewords: &[u8], ecl_data: &VersionEclData) -> Result<Vec<u8>, Error> {
+    let mut block_lengths =
+        vec![ecl_data.group1.codewords as usize; ecl_data.group1.blocks as usize];
+    if let Some(group2) = &ecl_data.group2 {
+        block_lengths.extend(vec![group2.codewords as usize; group2.blocks as usize]);
+    }
+
+    let mut blocks: Vec<Vec<u8>> = block_lengths
+        .iter()
+        .map(|len| Vec::with_capacity(len + ecl_data.ec_codewords_per_block))
+        .collect();
+    let mut remaining = codewords.iter();
+    for idx in 0..ecl_data.max_codewords_per_group() {
+        for (block, len) in blocks.iter_mut().zip(&block_lengths) {
+            if idx < *len {
+                block.push(*remaining.next().ok_or("Ran out of data codewords")?);
+            }
+        }
+    }
+    for _ in 0..ecl_data.ec_codewords_per_block {
+        for block in blocks.iter_mut() {
+            block.push(*remaining.next().ok_or("Ran out of EC codewords")?);
+        }
+    }
+
+    let mut data = Vec::with_capacity(ecl_data.data_codewords);
+    for (block, len) in blocks.iter_mut().zip(&block_lengths) {
+        correct_errors(block, ecl_data.ec_codewords_per_block)?;
+        data.extend_from_slice(&block[..*len]);
+    }
+    Ok(data)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+
+    fn read(&mut self, count: usize) -> Result<u16, Error> {
+        if count > self.remaining() {
+            return Err("Unexpected end of the bitstream".into());
+        }
+        let mut value = 0;
+        for _ in 0..count {
+            let bit = self.data[self.position / 8] >> (7 - self.position % 8) & 1;
+            value = value << 1 | bit as u16;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+fn decode_numeric(reader: &mut BitReader, count: usize, out: &mut Vec<u8>) -> Result<(), Error> {
+    let mut remaining = count;
+    while remaining > 0 {
+        let (digits, bitcount) = match remaining {
+            1 => (1, 4),
+            2 => (2, 7),
+            _ => (3, 10),
+        };
+        let value = reader.read(bitcount)?;
+        let text = format!("{:0width$}", value, width = digits);
+        if text.len() != digits {
+            return Err("Invalid numeric group".into());
+        }
+        out.extend_from_slice(text.as_bytes());
+        remaining -= digits;
+    }
+    Ok(())
+}
+
+fn alphanumeric_char(value: u16) -> Result<u8, Error> {
+    ALPHANUMERIC_CHARS
+        .get(value as usize)
+        .copied()
+        .ok_or_else(|| "Invalid alphanumeric value".into())
+}
+
+fn decode_alphanumeric(
+    reader: &mut BitReader,
+    count: usize,
+    out: &mut Vec<u8>,
+) -> Result<(), Error> {
+    for _ in 0..(count / 2) {
+        let value = reader.read(11)?;
+        out.push(alphanumeric_char(value / 45)?);
+        out.push(alphanumeric_char(value % 45)?);
+    }
+    if count % 2 == 1 {
+        out.push(alphanumeric_char(reader.read(6)?)?);
+    }
+    Ok(())
+}
+
+fn decode_bytes(reader: &mut BitReader, count: usize, out: &mut Vec<u8>) -> Result<(), Error> {
+    for _ in 0..count {
+        out.push(reader.read(8)? as u8);
+    }
+    Ok(())
+}
+
+/// Parses the data codewords back into the encoded payload, segment by segment.
+fn parse_segments(data: &[u8], version_num: u8) -> Result<Vec<u8>, Error> {
+    let mut reader = BitReader { data, position: 0 };
+    let mut out = Vec::new();
+    while reader.remaining() >= 4 {
+        // Spec: 8.4, Table 2
+        let encoding = match reader.read(4)? {
+            0b0000 => break,
+            0b0011 => {
+                // structured append: skip the symbol position, total and parity, since the data
+                // of this one symbol is all that's returned
+                reader.read(16)?;
+                continue;
+            }
+            0b0111 => {
+                // ECI: skip the designator, which is 1 to 3 bytes long as given by the number of
+                // leading 1 bits; the bytes are returned as they are
+                let first = reader.read(8)?;
+                let extra = (first as u8).leading_ones() as usize;
+                if extra > 2 {
+                    return Err("Invalid ECI designator".into());
+                }
+                reader.read(8 * extra)?;
+                continue;
+            }
+            0b0001 => QREncoding::Numeric,
+            0b0010 => QREncoding::Alphanumeric,
+            0b0100 => QREncoding::Bytes,
+            other => return Err(format!("Unsupported mode indicator {:04b}", other).into()),
+        };
+        let count = reader.read(encoding.character_count_bits(version_num))? as usize;
+        match encoding {
+            QREncoding::Numeric => decode_numeric(&mut reader, count, &mut out)?,
+            QREncoding::Alphanumeric => decode_alphanumeric(&mut reader, count, &mut out)?,
+            _ => decode_bytes(&mut reader, count, &mut out)?,
+        }
+    }
+    Ok(out)
+}
+
+fn decode_with(
+    matrix: &BitMatrix,
+    template: &QRCode,
+    ecl: &ErrorCorrectionLevel,
+    mask: u8,
+) -> Result<Vec<u8>, Error> {
+    let ecl_data = template.version.values_at_ecl(ecl);
+    let total_codewords = ecl_data.data_codewords + ecl_data.total_ec_codewords();
+    let codewords = read_codewords(matrix, template, mask, total_codewords)?;
+    let data = deinterleave(&codewords, ecl_data)?;
+    parse_segments(&data, template.version.num)
+}
+
+/// Decodes a clean module matrix (as produced by `QRCode::to_matrix`) of a known version back
+/// into the bytes it encodes.
+pub fn decode_matrix(matrix: &BitMatrix, version: &'static Version) -> Result<Vec<u8>, Error> {
+    let side = version.modules_per_side();
+    if matrix.len() != side || matrix.iter().any(|row| row.len() != side) {
+        return Err(format!(
+            "The matrix isn't {0}x{0} modules, as version {1} requires",
+            side, version.num
+        )
+        .into());
+    }
+
+    let template = QRCode::function_patterns(version);
+    match read_format_information(matrix) {
+        Some((ecl, mask)) => decode_with(matrix, &template, &ecl, mask),
+        None => Err("Couldn't read the format information".into()),
+    }
+}
+
+/// Picks the threshold that best separates the histogram into two classes, using Otsu's method.
+/// Returns `None` if the image only has a single shade.
+fn otsu_threshold(img: &GrayImage) -> Option<u8> {
+    let mut histogram = [0u64; 256];
+    for pixel in img.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let total: u64 = histogram.iter().sum();
+    let weighted_total: u64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, count)| value as u64 * count)
+        .sum();
+
+    let mut best = None;
+    let mut best_variance = 0.0;
+    let (mut background_count, mut background_sum) = (0u64, 0u64);
+    for (value, count) in histogram.iter().enumerate() {
+        background_count += count;
+        background_sum += value as u64 * count;
+        let foreground_count = total - background_count;
+        if background_count == 0 {
+            continue;
+        }
+        if foreground_count == 0 {
+            break;
+        }
+        let background_mean = background_sum as f64 / background_count as f64;
+        let foreground_mean = (weighted_total - background_sum) as f64 / foreground_count as f64;
+        let variance = background_count as f64
+            * foreground_count as f64
+            * (background_mean - foreground_mean).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = Some(value as u8);
+        }
+    }
+    best
+}
+
+/// Splits a grayscale image into dark (true) and light (false) pixels with a global Otsu
+/// threshold. The result is indexed by x and then y, like a module matrix.
+pub fn binarize(img: &GrayImage) -> BitMatrix {
+    let threshold = otsu_threshold(img);
+    (0..img.width())
+        .map(|x| {
+            (0..img.height())
+                .map(|y| threshold.is_some_and(|t| img.get_pixel(x, y)[0] <= t))
+                .collect()
+        })
+        .collect()
+}
+
+const MAX_FINDER_CANDIDATES: usize = 12;
+
+/// A finder pattern candidate: the center in pixels, the estimated module size, and how many
+/// scan lines crossed it.
+#[derive(Debug, Clone, Copy)]
+struct FinderPattern {
+    x: f64,
+    y: f64,
+    module_size: f64,
+    hits: usize,
+}
+
+/// Splits a line of pixels into runs of the same color, as (dark, start, length).
+fn runs(len: usize, dark_at: impl Fn(usize) -> bool) -> Vec<(bool, usize, usize)> {
+    let mut out: Vec<(bool, usize, usize)> = Vec::new();
+    for i in 0..len {
+        let dark = dark_at(i);
+        match out.last_mut() {
+            Some((run_dark, _, run_len)) if *run_dark == dark => *run_len += 1,
+            _ => out.push((dark, i, 1)),
+        }
+    }
+    out
+}
+
+/// Checks five consecutive runs (starting with a dark one) for the finder pattern's 1:1:3:1:1
+/// ratio, returning the center of the middle run and the module size if they match.
+fn finder_ratio(window: &[(bool, usize, usize)]) -> Option<(f64, f64)> {
+    if !window[0].0 {
+        return None;
+    }
+    let total: usize = window.iter().map(|(_, _, len)| len).sum();
+    let module_size = total as f64 / 7.0;
+    let matches = window
+        .iter()
+        .zip(&[1.0, 1.0, 3.0, 1.0, 1.0])
+        .all(|(&(_, _, len), expected)| {
+            (len as f64 - module_size * expected).abs() < module_size * expected / 2.0
+        });
+    if matches {
+        let (_, start, len) = window[2];
+        Some((start as f64 + len as f64 / 2.0, module_size))
+    } else {
+        None
+    }
+}
+
+/// Checks the line through `position` for a finder pattern whose middle run contains it.
+fn cross_check(len: usize, dark_at: impl Fn(usize) -> bool, position: usize) -> Option<(f64, f64)> {
+    let line = runs(len, dark_at);
+    let middle = line
+        .iter()
+        .position(|&(_, start, run_len)| start <= position && position < start + run_len)?;
+    if middle < 2 || middle + 2 >= line.len() {
+        return None;
+    }
+    finder_ratio(&line[middle - 2..=middle + 2])
+}
+
+/// Finds the three finder patterns, ordered top-left, top-right, bottom-left.
+fn find_finder_patterns(pixels: &BitMatrix) -> Result<[FinderPattern; 3], Error> {
+    let width = pixels.len();
+    let height = pixels.first().map_or(0, Vec::len);
+    if pixels.iter().any(|column| column.len() != height) {
+        return Err("The matrix's columns aren't all the same length".into());
+    }
+    let mut found: Vec<FinderPattern> = Vec::new();
+
+    for y in 0..height {
+        for window in runs(width, |x| pixels[x][y]).windows(5) {
+            let (center_x, horizontal_size) = match finder_ratio(window) {
+                Some(candidate) => candidate,
+                None => continue,
+            };
+            let column = center_x as usize;
+            let (center_y, vertical_size) = match cross_check(height, |y| pixels[column][y], y) {
+                Some(candidate) => candidate,
+                None => continue,
+            };
+            let module_size = (horizontal_size + vertical_size) / 2.0;
+            let existing = found.iter_mut().find(|finder| {
+                (finder.x - center_x).abs() < finder.module_size * 1.5
+                    && (finder.y - center_y).abs() < finder.module_size * 1.5
+                    && (finder.module_size - module_size).abs() < finder.module_size * 0.2
+            });
+            match existing {
+                Some(finder) => {
+                    let hits = finder.hits as f64;
+                    finder.x = (finder.x * hits + center_x) / (hits + 1.0);
+                    finder.y = (finder.y * hits + center_y) / (hits + 1.0);
+                    finder.module_size = (finder.module_size * hits + module_size) / (hits + 1.0);
+                    finder.hits += 1;
+                }
+                None => found.push(FinderPattern {
+                    x: center_x,
+                    y: center_y,
+                    module_size,
+                    hits: 1,
+                }),
+            }
+        }
+    }
+
+    if found.len() < 3 {
+        return Err(format!("Only found {} of the 3 finder patterns", found.len()).into());
+    }
+
+    // Data can occasionally look like a finder pattern too, so pick the best-supported trio that
+    // actually forms the right-angled corners of a square, preferring consistent module sizes.
+    found.sort_by_key(|finder| std::cmp::Reverse(finder.hits));
+    found.truncate(MAX_FINDER_CANDIDATES);
+    let mut best: Option<([FinderPattern; 3], usize, f64)> = None;
+    for (i, a) in found.iter().enumerate() {
+        for (j, b) in found.iter().enumerate().skip(i + 1) {
+            for c in found.iter().skip(j + 1) {
+                let ordered = order_finders(*a, *b, *c);
+                let hits = a.hits + b.hits + c.hits;
+                let spread = module_size_spread(&ordered);
+                let better = best.is_none_or(|(_, most, narrowest)| {
+                    hits > most || (hits == most && spread < narrowest)
+                });
+                if better && finders_plausible(&ordered) {
+                    best = Some((ordered, hits, spread));
+                }
+            }
+        }
+    }
+    best.map(|(finders, _, _)| finders)
+        .ok_or_else(|| "Couldn't find 3 finder patterns arranged like a QR code".into())
+}
+
+fn finder_distance(p: &FinderPattern, q: &FinderPattern) -> f64 {
+    (p.x - q.x).hypot(p.y - q.y)
+}
+
+/// Orders three finder patterns as top-left, top-right, bottom-left.
+fn order_finders(a: FinderPattern, b: FinderPattern, c: FinderPattern) -> [FinderPattern; 3] {
+    // The top-left finder is the corner opposite the longest side of the triangle
+    let (ab, ac, bc) = (
+        finder_distance(&a, &b),
+        finder_distance(&a, &c),
+        finder_distance(&b, &c),
+    );
+    let (corner, first, second) = if bc >= ab.max(ac) {
+        (a, b, c)
+    } else if ac >= ab {
+        (b, a, c)
+    } else {
+        (c, a, b)
+    };
+    // With y pointing down, top-right -> bottom-left turns clockwise around the top-left
+    let cross =
+        (first.x - corner.x) * (second.y - corner.y) - (first.y - corner.y) * (second.x - corner.x);
+    if cross > 0.0 {
+        [corner, first, second]
+    } else {
+        [corner, second, first]
+    }
+}
+
+/// The ratio between the largest and smallest module sizes of the finders.
+fn module_size_spread(finders: &[FinderPattern; 3]) -> f64 {
+    let sizes = finders.iter().map(|finder| finder.module_size);
+    let smallest = sizes.clone().fold(f64::INFINITY, f64::min);
+    let largest = sizes.fold(0.0, f64::max);
+    largest / smallest
+}
+
+/// Whether the finders have similar module sizes and sit at the corners of a square big enough
+/// to hold a version 1 code.
+fn finders_plausible(finders: &[FinderPattern; 3]) -> bool {
+    let [top_left, top_right, bottom_left] = finders;
+    let across = finder_distance(top_left, top_right);
+    let down = finder_distance(top_left, bottom_left);
+    let diagonal = finder_distance(top_right, bottom_left);
+    module_size_spread(finders) < 1.25
+        && (across - down).abs() < across.max(down) * 0.1
+        && (diagonal - (across + down) / 2.0 * 2f64.sqrt()).abs() < diagonal * 0.1
+        && across / top_left.module_size >= 13.0
+}
+
+/// Locates the centers of the three finder patterns in a binarized image (or a module matrix),
+/// ordered top-left, top-right, bottom-left, by scanning for their 1:1:3:1:1 ratio along rows
+/// and cross-checking it along columns.
+pub fn locate_finders(matrix: &BitMatrix) -> Result<[(usize, usize); 3], Error> {
+    let finders = find_finder_patterns(matrix)?;
+    Ok([
+        (finders[0].x as usize, finders[0].y as usize),
+        (finders[1].x as usize, finders[1].y as usize),
+        (finders[2].x as usize, finders[2].y as usize),
+    ])
+}
+
+/// Interprets decoded bytes as UTF-8 if possible, falling back to ISO-8859-1 like the encoder.
+fn bytes_to_string(bytes: Vec<u8>) -> Result<String, Error> {
+    String::from_utf8(bytes).or_else(|e| {
+        ISO_8859_1
+            .decode(e.as_bytes(), DecoderTrap::Strict)
+            .map_err(|e| e.into_owned().into())
+    })
+}
+
+/// Decodes a clean image of a code, such as one produced by `save_qrcode`. The code must be
+/// axis-aligned and unrotated, surrounded by a light quiet zone. The module grid is derived from
+/// the finder patterns; the module size hint (in pixels) just helps pin down the version.
+pub fn from_image(img: &RgbImage, module_size_hint: Option<u32>) -> Result<String, Error> {
+    let pixels = binarize(&grayscale(img));
+    let [top_left, top_right, bottom_left] = find_finder_patterns(&pixels)?;
+    let module_size = match module_size_hint {
+        Some(0) => return Err("The module size must be at least one pixel".into()),
+        Some(size) => size as f64,
+        None => (top_left.module_size + top_right.module_size + bottom_left.module_size) / 3.0,
+    };
+
+    // The finder centers are 3.5 modules in from the edges
+    let across = (top_right.x - top_left.x).max(bottom_left.y - top_left.y) / module_size + 7.0;
+    let version_num = ((across - 17.0) / 4.0).round();
+    if !(1.0..=40.0).contains(&version_num) {
+        return Err(format!(
+            "The code is about {:.0} modules across, which isn't a valid size",
+            across
+        )
+        .into());
+    }
+    let version = Version::by_num(version_num as usize);
+    let side = version.modules_per_side();
+
+    let pitch_x = (top_right.x - top_left.x) / (side - 7) as f64;
+    let pitch_y = (bottom_left.y - top_left.y) / (side - 7) as f64;
+    let left = top_left.x - 3.5 * pitch_x;
+    let top = top_left.y - 3.5 * pitch_y;
+    let sample = |origin: f64, pitch: f64, module: usize, limit: usize| {
+        ((origin + (module as f64 + 0.5) * pitch).max(0.0) as usize).min(limit - 1)
+    };
+    let matrix = (0..side)
+        .map(|x| {
+            let px = sample(left, pitch_x, x, pixels.len());
+            (0..side)
+                .map(|y| pixels[px][sample(top, pitch_y, y, pixels[px].len())])
+                .collect()
+        })
+        .collect();
+    bytes_to_string(decode_matrix(&matrix, version)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_qr_code;
+
+    fn round_trip(data: &str, ecl: ErrorCorrectionLevel) -> Vec<u8> {
+        let code = create_qr_code(data, ecl).unwrap();
+        decode_matrix(&code.to_matrix(), code.version).unwrap()
+    }
+
+    #[test]
+    fn test_new_writes_format_information() {
+        let version = Version::by_num(2);
+        let ecl = ErrorCorrectionLevel::Quartile;
+        let codewords = crate::qr::encode::QRBitstreamEncoder::new("FORMAT HERE")
+            .codewords(version, &ecl)
+            .unwrap();
+        let bitstream =
+            crate::qr::error_correction::bitstream_with_ec(codewords, version.values_at_ecl(&ecl))
+                .unwrap();
+        let code = QRCode::new(version, ecl, bitstream);
+        assert_eq!(
+            read_format_information(&code.to_matrix()),
+            Some((ecl, code.mask.unwrap()))
+        );
+        assert_eq!(
+            decode_matrix(&code.to_matrix(), version).unwrap(),
+            b"FORMAT HERE"
+        );
+    }
+
+    #[test]
+    fn test_format_copies_round_trip() {
+        let bits = format_information(&Low, 4);
+        for num in &[1, 7, 40] {
+            let mut code = QRCode::function_patterns(Version::by_num(*num));
+            code.insert_format_information(bits);
+            let matrix = code.to_matrix();
+            let side = matrix.len();
+            assert_eq!(read_format_copies(&matrix), (bits, bits));
+            assert_eq!(read_format_information(&matrix), Some((Low, 4)));
+            // the most significant bit starts both copies, the least significant ends them
+            assert!(matrix[0][8] && matrix[8][side - 1]);
+            assert!(matrix[8][0] && matrix[side - 1][8]);
+            // 110_0110_0010_1111: bit 7 is the last of the top right row, bit 8 starts the column
+            assert!(!matrix[side - 8][8] && !matrix[8][side - 7]);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_hello() {
+        assert_eq!(round_trip("Hello", Medium), b"Hello");
+    }
+
+    #[test]
+    fn test_round_trip_modes() {
+        assert_eq!(round_trip("01234567", Medium), b"01234567");
+        assert_eq!(round_trip("HELLO WORLD", Quartile), b"HELLO WORLD");
+        assert_eq!(
+            round_trip("Hello, world! I am a weirdly complicated QR code!", High),
+            b"Hello, world! I am a weirdly complicated QR code!"
+        );
+    }
+
+    mod image {
+        use super::*;
+        use crate::qr::image::{to_png, RenderOptions};
+
+        /// The code as it comes back from a saved PNG, encoded and loaded in memory.
+        fn saved_image(data: &str, ecl: ErrorCorrectionLevel) -> RgbImage {
+            let code = create_qr_code(data, ecl).unwrap();
+            let png = to_png(&code, &RenderOptions::default()).unwrap();
+            ::image::load_from_memory(&png).unwrap().to_rgb()
+        }
+
+        #[test]
+        fn test_from_saved_png() {
+            let img = saved_image("Hello, world!", Medium);
+            assert_eq!(from_image(&img, None).unwrap(), "Hello, world!");
+        }
+
+        #[test]
+        fn test_from_saved_png_with_hint() {
+            let img = saved_image("HELLO WORLD 0123456789 HELLO WORLD", High);
+            assert_eq!(
+                from_image(&img, Some(4)).unwrap(),
+                "HELLO WORLD 0123456789 HELLO WORLD"
+            );
+        }
+
+        #[test]
+        fn test_damaged_images() {
+            // noise and crops of a real code, as a quick stand-in for the fuzz target: decoding
+            // may fail, but mustn't panic
+            let img = saved_image("Damaged code", Medium);
+            let mut state = 0x2545_f491_u32;
+            for round in 0..50 {
+                let mut damaged = img.clone();
+                for _ in 0..round * 20 {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    let (x, y) = (state % img.width(), (state >> 8) % img.height());
+                    damaged.put_pixel(x, y, ::image::Rgb([(state >> 16) as u8; 3]));
+                }
+                let _ = from_image(&damaged, None);
+                let width = img.width() * (round + 1) / 51;
+                let cropped = ::image::imageops::crop(&mut damaged, 0, 0, width, img.height());
+                let _ = from_image(&cropped.to_image(), Some(round));
+            }
+        }
+
+        #[test]
+        fn test_blank_image() {
+            let img = RgbImage::from_pixel(100, 100, ::image::Rgb([255, 255, 255]));
+            assert!(from_image(&img, None).is_err());
+        }
+    }
+
+    mod locate_finders {
+        use super::*;
+
+        #[test]
+        fn test_version_1() {
+            let code = create_qr_code("Hello", Medium).unwrap();
+            assert_eq!(code.version.num, 1);
+            assert_eq!(
+                locate_finders(&code.to_matrix()).unwrap(),
+                [(3, 3), (17, 3), (3, 17)]
+            );
+        }
+
+        #[test]
+        fn test_version_5() {
+            let code = create_qr_code(
+                "Hello, world! I am a weirdly complicated QR code!",
+                Quartile,
+            )
+            .unwrap();
+            assert_eq!(code.version.num, 5);
+            assert_eq!(
+                locate_finders(&code.to_matrix()).unwrap(),
+                [(3, 3), (33, 3), (3, 33)]
+            );
+        }
+
+        #[test]
+        fn test_missing_finder() {
+            let code = create_qr_code("Hello", Medium).unwrap();
+            let mut matrix = code.to_matrix();
+            for column in matrix.iter_mut().take(7) {
+                for module in column.iter_mut().take(7) {
+                    *module = false;
+                }
+            }
+            assert!(locate_finders(&matrix).is_err());
+        }
+
+        #[test]
+        fn test_ragged_matrix() {
+            let mut matrix = create_qr_code("Hello", Medium).unwrap().to_matrix();
+            matrix[20].truncate(10);
+            assert!(locate_finders(&matrix).is_err());
+            assert!(decode_matrix(&matrix, Version::by_num(1)).is_err());
+        }
+    }
+
+    mod binarize {
+        use super::*;
+        use ::image::Luma;
+
+        #[test]
+        fn test_gradient() {
+            let img = GrayImage::from_fn(256, 8, |x, _| Luma([x as u8]));
+            let pixels = binarize(&img);
+            for (x, column) in pixels.iter().enumerate() {
+                assert!(column.iter().all(|&dark| dark == (x < 128)), "column {}", x);
+            }
+        }
+
+        #[test]
+        fn test_two_tone() {
+            let img = GrayImage::from_fn(20, 20, |x, y| {
+                Luma([if x < 10 {
+                    60 + (y % 3) as u8
+                } else {
+                    190 - (y % 5) as u8
+                }])
+            });
+            let pixels = binarize(&img);
+            for (x, column) in pixels.iter().enumerate() {
+                assert!(column.iter().all(|&dark| dark == (x < 10)));
+            }
+        }
+
+        #[test]
+        fn test_single_shade() {
+            let img = GrayImage::from_pixel(5, 5, Luma([0]));
+            assert!(binarize(&img).iter().flatten().all(|&dark| !dark));
+        }
+    }
+
+    #[test]
+    fn test_wrong_size() {
+        let code = create_qr_code("Hello", Low).unwrap();
+        assert!(decode_matrix(&code.to_matrix(), Version::by_num(2)).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use crate::qr::pattern::QRCode;
+
+/// A single drawing operation for vector output devices.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawCommand {
+    /// A filled rectangle, with its top left corner at `x`, `y`.
+    Rect { x: f32, y: f32, w: f32, h: f32 },
+}
+
+pub(crate) fn draw_commands(code: &QRCode, module_size: f32) -> Vec<DrawCommand> {
+    code.dark_runs()
+        .into_iter()
+        .map(|((x, y), length)| DrawCommand::Rect {
+            x: x as f32 * module_size,
+            y: y as f32 * module_size,
+            w: length as f32 * module_size,
+            h: module_size,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_qr_code;
+    use crate::qr::error_correction::ErrorCorrectionLevel;
+
+    #[test]
+    fn test_merged_commands() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let commands = code.to_draw_commands(2.0);
+        let black_modules = code.modules().filter(|(_, m)| m.black()).count();
+        assert!(commands.len() <= black_modules);
+
+        // the top edge of the first finder is a single stroke
+        assert_eq!(
+            commands[0],
+            DrawCommand::Rect {
+                x: 0.0,
+                y: 0.0,
+                w: 14.0,
+                h: 2.0
+            }
+        );
+        let area: f32 = commands
+            .iter()
+            .map(|DrawCommand::Rect { w, h, .. }| w * h)
+            .sum();
+        assert_eq!(area, black_modules as f32 * 4.0);
+    }
+}
@@ -14,7 +14,7 @@ fn div_rem(a: usize, b: usize) -> (usize, usize) {
     (a / b, a % b)
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum QREncoding {
     Numeric,
     Alphanumeric,
@@ -73,35 +73,41 @@ fn alphanumeric_char_value(character: &char) -> Option<u16> {
     }
 }
 
-/// Performs encoding in Numeric mode, as described in section 8.4.2 of the spec.
+/// Performs encoding in Numeric mode, as described in section 8.4.2 of the spec. Digit strings
+/// can be very long, so the bits are gathered into whole bytes rather than pushed one at a time.
 fn encode_numeric(data: &str) -> QREncodedData {
-    let mut cur = data;
-    let (tria, remainder) = div_rem(data.len(), 2);
-    let mut out = BitVec::with_capacity(
-        tria * 10
-            + if remainder == 2 {
-                7
-            } else if remainder == 1 {
-                4
-            } else {
-                0
-            },
-    );
-    while !cur.is_empty() {
-        let (three_digits, rest) = cur.split_at(min(3, cur.len()));
-
-        let bitcount = if !rest.is_empty() || three_digits.len() == 3 {
-            10
-        } else if three_digits.len() == 2 {
-            7
-        } else {
-            4
+    let digits = data.as_bytes();
+    let bit_len = digits.len() / 3 * 10
+        + match digits.len() % 3 {
+            2 => 7,
+            1 => 4,
+            _ => 0,
         };
-
-        let value = three_digits.parse::<u16>().unwrap() << (16 - bitcount);
-        insert_into_data(&mut out, value, bitcount);
-        cur = rest;
+    let mut bytes = Vec::with_capacity(bit_len.div_ceil(8));
+    let (mut pending, mut pending_bits) = (0u32, 0);
+    for group in digits.chunks(3) {
+        let value = group
+            .iter()
+            .fold(0, |value, digit| value * 10 + (digit - b'0') as u32);
+        let bitcount = match group.len() {
+            3 => 10,
+            2 => 7,
+            _ => 4,
+        };
+        pending = pending << bitcount | value;
+        pending_bits += bitcount;
+        while pending_bits >= 8 {
+            pending_bits -= 8;
+            // the bitstream keeps its first bit in the least significant bit of each byte
+            bytes.push(((pending >> pending_bits) as u8).reverse_bits());
+        }
+        pending &= (1 << pending_bits) - 1;
     }
+    if pending_bits > 0 {
+        bytes.push(((pending << (8 - pending_bits)) as u8).reverse_bits());
+    }
+    let mut out = BitVec::from_vec(bytes);
+    out.truncate(bit_len);
     out
 }
 
@@ -132,34 +138,81 @@ fn encode_alphanumeric(data: &str) -> QREncodedData {
     out
 }
 
+/// Which character set text is converted to in Bytes mode.
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ByteCharset {
+    /// ISO-8859-1 when the text can be represented in it, UTF-8 otherwise.
+    Auto,
+    /// Always ISO-8859-1; text outside of it is an error.
+    Latin1,
+    /// Always UTF-8, which is what many scanners assume.
+    Utf8,
+}
+
+/// The ECI designator for UTF-8.
+pub const UTF8_ECI: u32 = 26;
+
+/// Whether Bytes mode would write the text as UTF-8 that differs from what readers assume by
+/// default, ISO-8859-1, so it needs a UTF-8 ECI header to come out right. Plain ASCII reads the
+/// same either way.
+pub(crate) fn needs_utf8_eci(data: &str, charset: ByteCharset) -> bool {
+    if data.is_ascii() || choose_encoding(data) != Bytes {
+        return false;
+    }
+    match charset {
+        ByteCharset::Auto => ISO_8859_1.encode(data, EncoderTrap::Strict).is_err(),
+        ByteCharset::Latin1 => false,
+        ByteCharset::Utf8 => true,
+    }
+}
+
 /// Performs encoding in Bytes mode, as described in section 8.4.4 of the spec.
-fn encode_bytes(data: &str) -> QREncodedData {
-    let bytes = ISO_8859_1
-        .encode(data, EncoderTrap::Strict)
-        .or_else(|_| UTF_8.encode(data, EncoderTrap::Replace))
-        .unwrap();
-    bytes_to_bitvec(bytes)
+fn encode_bytes(data: &str, charset: ByteCharset) -> Result<QREncodedData, Error> {
+    let bytes = match charset {
+        ByteCharset::Auto => ISO_8859_1
+            .encode(data, EncoderTrap::Strict)
+            .or_else(|_| UTF_8.encode(data, EncoderTrap::Replace)),
+        ByteCharset::Latin1 => ISO_8859_1.encode(data, EncoderTrap::Strict),
+        ByteCharset::Utf8 => UTF_8.encode(data, EncoderTrap::Replace),
+    }
+    .map_err(|_| "The data can't be represented in ISO-8859-1")?;
+    Ok(bytes_to_bitvec(bytes))
 }
 
 impl QREncoding {
     fn allows_char(&self, character: &char) -> bool {
         match self {
-            Numeric => character.is_digit(10),
-            Alphanumeric => alphanumeric_char_value(&character).is_some(),
+            Numeric => character.is_ascii_digit(),
+            Alphanumeric => alphanumeric_char_value(character).is_some(),
             Bytes => true,
             _ => unimplemented!(),
         }
     }
 
-    fn encode(&self, data: &str) -> QREncodedData {
+    fn encode(&self, data: &str, charset: ByteCharset) -> Result<QREncodedData, Error> {
         match self {
-            Numeric => encode_numeric(data),
-            Alphanumeric => encode_alphanumeric(data),
-            Bytes => encode_bytes(data),
+            Numeric => Ok(encode_numeric(data)),
+            Alphanumeric => Ok(encode_alphanumeric(data)),
+            Bytes => encode_bytes(data, charset),
             _ => unimplemented!(),
         }
     }
 
+    /// How many sixths of a bit a character takes in this mode, which keeps the 10 bits per 3
+    /// digits of Numeric mode and the 11 bits per 2 characters of Alphanumeric mode whole, or
+    /// `None` if the mode can't hold it. Bytes mode takes a byte per character of text that fits
+    /// in ISO-8859-1, and the character's UTF-8 length otherwise.
+    fn character_sixths(&self, character: char, latin1: bool) -> Option<usize> {
+        match self {
+            Numeric if character.is_ascii_digit() => Some(20),
+            Alphanumeric if alphanumeric_char_value(&character).is_some() => Some(33),
+            Bytes if latin1 => Some(48),
+            Bytes => Some(48 * character.len_utf8()),
+            _ => None,
+        }
+    }
+
     fn mode(&self) -> QREncodedData {
         // Spec: 8.4, Table 2
         match self {
@@ -170,7 +223,18 @@ impl QREncoding {
         }
     }
 
-    fn character_count_bits(&self, version_num: u8) -> usize {
+    /// The value of the character count indicator for the data, given the bits it was encoded
+    /// to. Bytes mode counts bytes after character set conversion, which needn't match the UTF-8
+    /// length, and Kanji mode counts double-byte characters rather than bytes.
+    fn character_count(&self, data: &str, encoded_data: &QREncodedData) -> usize {
+        match self {
+            Bytes => encoded_data.len() / 8,
+            Kanji => data.chars().count(),
+            Numeric | Alphanumeric => data.len(),
+        }
+    }
+
+    pub(crate) fn character_count_bits(&self, version_num: u8) -> usize {
         // Spec: 8.4, Table 3
         let (tier_1, tier_2, tier_3) = match self {
             Numeric => (10, 12, 14),
@@ -188,7 +252,6 @@ impl QREncoding {
 }
 
 /// Selects the encoding based on the input data. Currently Kanji mode is unsupported.
-/// ECI mode support is possible in the future, I suppose, but unlikely.
 fn choose_encoding(data: &str) -> QREncoding {
     let mut can_be_numeric = true;
     let mut can_be_alphanumeric = true;
@@ -210,32 +273,351 @@ fn choose_encoding(data: &str) -> QREncoding {
     }
 }
 
+/// Splits the data into the segments that take the fewest bits at this version, switching modes
+/// wherever the characters saved outweigh the extra mode and character count indicators. Those
+/// indicators grow at versions 10 and 27, so the same data can be worth splitting at one version
+/// and not the next. Data that only needs one mode gets a single segment in the most compact
+/// mode that holds all of it. Bytes segments are costed for the `ByteCharset::Auto` character set
+/// of the whole data, which `QRBitstreamEncoder::from_segments` can beat slightly when a Bytes
+/// segment on its own fits in ISO-8859-1.
+pub fn choose_encoding_for_version(data: &str, version_num: u8) -> Vec<Segment> {
+    const MODES: [QREncoding; 3] = [Numeric, Alphanumeric, Bytes];
+    let characters: Vec<char> = data.chars().collect();
+    if characters.is_empty() {
+        return vec![Segment {
+            mode: choose_encoding(data),
+            data: String::new(),
+        }];
+    }
+    let latin1 = ISO_8859_1.encode(data, EncoderTrap::Strict).is_ok();
+    let header_sixths = |mode: QREncoding| (4 + mode.character_count_bits(version_num)) * 6;
+
+    // the least sixths of a bit the characters so far take when the last one is in each mode,
+    // and for each character, the mode of the one before it on that cheapest path
+    let mut costs = MODES.map(header_sixths);
+    let mut previous_modes: Vec<[usize; 3]> = Vec::with_capacity(characters.len());
+    for (position, &character) in characters.iter().enumerate() {
+        let mut next_costs = [usize::MAX; 3];
+        let mut previous = [0; 3];
+        for (to, mode) in MODES.iter().enumerate() {
+            let character_cost = match mode.character_sixths(character, latin1) {
+                Some(cost) => cost,
+                None => continue,
+            };
+            for (from, &cost) in costs.iter().enumerate() {
+                if cost == usize::MAX {
+                    continue;
+                }
+                // a segment ends on a whole bit, before the next one's indicators
+                let cost = match (position, from == to) {
+                    (0, _) if from != to => continue,
+                    (0, _) | (_, true) => cost,
+                    _ => cost.div_ceil(6) * 6 + header_sixths(*mode),
+                };
+                // staying in the same mode wins ties, for fewer segments
+                if cost + character_cost < next_costs[to]
+                    || (cost + character_cost == next_costs[to] && from == to)
+                {
+                    next_costs[to] = cost + character_cost;
+                    previous[to] = from;
+                }
+            }
+        }
+        costs = next_costs;
+        previous_modes.push(previous);
+    }
+
+    let mut mode = (0..MODES.len()).min_by_key(|&mode| costs[mode]).unwrap();
+    let mut segments: Vec<Segment> = Vec::new();
+    for (position, &character) in characters.iter().enumerate().rev() {
+        match segments.last_mut() {
+            Some(segment) if segment.mode == MODES[mode] => segment.data.insert(0, character),
+            _ => segments.push(Segment {
+                mode: MODES[mode],
+                data: character.to_string(),
+            }),
+        }
+        mode = previous_modes[position][mode];
+    }
+    segments.reverse();
+    segments
+}
+
+/// A writing system, as far as telling them apart helps explain why text needs Bytes mode.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Han,
+    Kana,
+    Hangul,
+    Other,
+}
+
+impl Script {
+    /// The script of a letter, or `None` for digits, punctuation, whitespace and symbols, which
+    /// are shared between scripts.
+    fn of(character: char) -> Option<Script> {
+        if !character.is_alphabetic() {
+            return None;
+        }
+        Some(match character as u32 {
+            0x0000..=0x024F | 0x1E00..=0x1EFF => Script::Latin,
+            0x0370..=0x03FF | 0x1F00..=0x1FFF => Script::Greek,
+            0x0400..=0x052F => Script::Cyrillic,
+            0x0590..=0x05FF => Script::Hebrew,
+            0x0600..=0x06FF | 0x0750..=0x077F => Script::Arabic,
+            0x3400..=0x4DBF | 0x4E00..=0x9FFF => Script::Han,
+            0x3040..=0x30FF => Script::Kana,
+            0x1100..=0x11FF | 0xAC00..=0xD7AF => Script::Hangul,
+            _ => Script::Other,
+        })
+    }
+}
+
+/// What `script_analysis` found out about some text.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ScriptReport {
+    /// The scripts of the letters in the text, in the order they first appear.
+    pub scripts: Vec<Script>,
+    /// The mode the text will be encoded in.
+    pub encoding: QREncoding,
+}
+
+impl ScriptReport {
+    /// Whether letters from more than one script appear, which always means Bytes mode.
+    pub fn is_mixed(&self) -> bool {
+        self.scripts.len() > 1
+    }
+
+    pub fn requires_bytes(&self) -> bool {
+        self.encoding == Bytes
+    }
+}
+
+/// Reports which scripts the text's letters come from and the mode it needs, so interfaces can
+/// explain why some text produces a larger code than expected.
+pub fn script_analysis(data: &str) -> ScriptReport {
+    let mut scripts = Vec::new();
+    for script in data.chars().filter_map(Script::of) {
+        if !scripts.contains(&script) {
+            scripts.push(script);
+        }
+    }
+    ScriptReport {
+        scripts,
+        encoding: choose_encoding(data),
+    }
+}
+
+/// The header that marks a symbol as one of a structured append set: up to 16 symbols whose
+/// data a reader joins back together in order. Spec: 8.3.6
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StructuredAppend {
+    /// The position of the symbol in the set, counting from 0.
+    pub index: u8,
+    /// The number of symbols in the set, from 1 to 16.
+    pub total: u8,
+    /// The XOR of every byte of the whole message, the same in each symbol of the set.
+    pub parity: u8,
+}
+
+/// The structured append mode indicator, symbol position, total and parity.
+const STRUCTURED_APPEND_HEADER_BITS: usize = 4 + 4 + 4 + 8;
+
+/// The largest ECI designator the spec allows.
+pub const MAX_ECI_DESIGNATOR: u32 = 999_999;
+
+/// Writes an ECI designator in as few bytes as it fits in: the number of leading 1 bits of the
+/// first byte says how many more follow. Spec: 8.4.1.1, Table 4
+fn eci_designator_bytes(designator: u32) -> Vec<u8> {
+    match designator {
+        0..=127 => vec![designator as u8],
+        128..=16383 => vec![0x80 | (designator >> 8) as u8, designator as u8],
+        _ => vec![
+            0xC0 | (designator >> 16) as u8,
+            (designator >> 8) as u8,
+            designator as u8,
+        ],
+    }
+}
+
+/// A run of data to encode in a single mode, for codes that switch modes partway through, like
+/// Bytes for a name followed by Numeric for a long number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub mode: QREncoding,
+    pub data: String,
+}
+
+/// A segment's data, encoded in its mode, and the value of its character count indicator.
 #[derive(Debug)]
-pub struct QRBitstreamEncoder {
-    pub data: QREncodedData,
+pub struct EncodedSegment {
     pub encoding: QREncoding,
-    pub character_count: u16,
+    pub data: QREncodedData,
+    pub character_count: usize,
+}
+
+#[derive(Debug)]
+pub struct QRBitstreamEncoder {
+    /// The data in order, each segment written with its own mode and count indicators. There's
+    /// always at least one.
+    segments: Vec<EncodedSegment>,
+    pub structured_append: Option<StructuredAppend>,
+    /// The ECI designator telling readers how to interpret the data, e.g. 26 for UTF-8.
+    pub eci: Option<u32>,
 }
 
 impl QRBitstreamEncoder {
     pub fn new(data: &str) -> QRBitstreamEncoder {
-        let encoding = choose_encoding(&data);
-        let encoded_data = encoding.encode(&data);
+        Self::with_charset(data, ByteCharset::Auto).unwrap()
+    }
+
+    /// Like `new`, but converts text to bytes with the given character set if Bytes mode is used.
+    pub fn with_charset(data: &str, charset: ByteCharset) -> Result<QRBitstreamEncoder, Error> {
+        Self::with_mode_and_charset(data, choose_encoding(data), charset)
+    }
+
+    /// Encodes the data in the given mode instead of the most compact one it allows, e.g. to keep
+    /// an all-digit URL segment in Bytes mode. Fails on the first character the mode can't hold.
+    pub fn with_mode(data: &str, mode: QREncoding) -> Result<QRBitstreamEncoder, Error> {
+        Self::with_mode_and_charset(data, mode, ByteCharset::Auto)
+    }
+
+    fn with_mode_and_charset(
+        data: &str,
+        encoding: QREncoding,
+        charset: ByteCharset,
+    ) -> Result<QRBitstreamEncoder, Error> {
+        if encoding == Kanji {
+            return Err("Kanji mode isn't supported yet".into());
+        }
+        if let Some(character) = data
+            .chars()
+            .find(|character| !encoding.allows_char(character))
+        {
+            return Err(format!("{:?} can't be encoded in {:?} mode", character, encoding).into());
+        }
+        let encoded_data = encoding.encode(data, charset)?;
+        let character_count = encoding.character_count(data, &encoded_data);
+        Ok(QRBitstreamEncoder {
+            segments: vec![EncodedSegment {
+                encoding,
+                data: encoded_data,
+                character_count,
+            }],
+            structured_append: None,
+            eci: None,
+        })
+    }
+
+    /// Encodes arbitrary bytes in Bytes mode, without any character set conversion.
+    pub fn from_bytes(data: &[u8]) -> QRBitstreamEncoder {
         QRBitstreamEncoder {
-            data: encoded_data,
-            encoding,
-            character_count: data.len() as u16,
+            segments: vec![EncodedSegment {
+                encoding: Bytes,
+                data: bytes_to_bitvec(data.to_vec()),
+                character_count: data.len(),
+            }],
+            structured_append: None,
+            eci: None,
         }
     }
 
+    /// Encodes each segment in its own mode, one after the other, so data that mixes, say, text
+    /// and long runs of digits can use the densest mode for each part. Bytes segments use the
+    /// `ByteCharset::Auto` character set. Fails if a segment has characters its mode can't hold.
+    pub fn from_segments(segments: Vec<Segment>) -> Result<QRBitstreamEncoder, Error> {
+        let mut encoded = Vec::with_capacity(segments.len());
+        for segment in segments {
+            let encoder = QRBitstreamEncoder::with_mode(&segment.data, segment.mode)?;
+            encoded.extend(encoder.segments);
+        }
+        if encoded.is_empty() {
+            return Err("There has to be at least one segment".into());
+        }
+        Ok(QRBitstreamEncoder {
+            segments: encoded,
+            structured_append: None,
+            eci: None,
+        })
+    }
+
+    /// The encoded segments, in the order they're written.
+    pub fn segments(&self) -> &[EncodedSegment] {
+        &self.segments
+    }
+
+    /// The mode of the first segment, which is the only one unless the encoder came from
+    /// `from_segments`.
+    pub fn encoding(&self) -> QREncoding {
+        self.segments[0].encoding
+    }
+
+    /// The sum of the segments' character counts.
+    pub fn character_count(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| segment.character_count)
+            .sum()
+    }
+
+    /// Marks the symbol as part of a structured append set, which takes 20 bits of its capacity.
+    pub fn with_structured_append(mut self, header: StructuredAppend) -> Self {
+        self.structured_append = Some(header);
+        self
+    }
+
+    /// Adds an ECI header selecting how readers should interpret the data, such as 26 for UTF-8
+    /// or 3 for ISO-8859-1. It takes 12 to 28 bits of capacity, depending on the designator.
+    pub fn with_eci(mut self, designator: u32) -> Result<Self, Error> {
+        if designator > MAX_ECI_DESIGNATOR {
+            return Err(format!(
+                "ECI designator {} is out of range; the largest is {}",
+                designator, MAX_ECI_DESIGNATOR
+            )
+            .into());
+        }
+        self.eci = Some(designator);
+        Ok(self)
+    }
+
     fn bitstream_length_before_terminator(&self, version_num: u8) -> usize {
-        // mode + character count indicator + data
-        4 + self.encoding.character_count_bits(version_num) + self.data.len()
+        let mut header = match self.structured_append {
+            Some(_) => STRUCTURED_APPEND_HEADER_BITS,
+            None => 0,
+        };
+        if let Some(designator) = self.eci {
+            header += 4 + eci_designator_bytes(designator).len() * 8;
+        }
+        // header, then mode + character count indicator + data for each segment
+        header
+            + self
+                .segments
+                .iter()
+                .map(|segment| {
+                    4 + segment.encoding.character_count_bits(version_num) + segment.data.len()
+                })
+                .sum::<usize>()
     }
 
     pub fn codeword_count_before_padding(&self, version_num: u8) -> usize {
         let character_count_bits = self.bitstream_length_before_terminator(version_num);
-        ((character_count_bits + (8 - 1)) / 8) as usize // divide rounding up
+        character_count_bits.div_ceil(8)
+    }
+
+    /// The number of 0xEC/0x11 padding codewords `bitstream` will add to fill the version.
+    pub fn padding_codeword_count(&self, version: &Version, ecl: &ErrorCorrectionLevel) -> usize {
+        let capacity = version.codeword_count(ecl);
+        // the terminator gets cut short if the data nearly fills the capacity
+        let with_terminator = min(
+            self.bitstream_length_before_terminator(version.num) + 4,
+            capacity * 8,
+        );
+        capacity.saturating_sub(with_terminator.div_ceil(8))
     }
 
     pub fn bitstream(
@@ -245,23 +627,53 @@ impl QRBitstreamEncoder {
     ) -> Result<QREncodedData, Error> {
         let codeword_count = version.codeword_count(ecl);
         let mut bitstream = BitVec::with_capacity(codeword_count * 8);
-        let mut mode = self.encoding.mode();
-
-        let char_count_value = self.character_count;
-        let char_count_size = self.encoding.character_count_bits(version.num);
-        let mut char_count_indicator = BitVec::with_capacity(char_count_size);
-        insert_into_data(
-            &mut char_count_indicator,
-            char_count_value << (16 - char_count_size),
-            char_count_size,
-        );
 
-        bitstream.append(&mut mode);
-        bitstream.append(&mut char_count_indicator);
-        bitstream.append(&mut self.data);
+        if let Some(header) = self.structured_append {
+            // Spec: 8.4, Table 2 gives 0011 as the structured append mode indicator
+            insert_into_data(&mut bitstream, 0b0011 << 12, 4);
+            insert_into_data(&mut bitstream, (header.index as u16) << 12, 4);
+            insert_into_data(&mut bitstream, (header.total as u16 - 1) << 12, 4);
+            insert_into_data(&mut bitstream, (header.parity as u16) << 8, 8);
+        }
+        if let Some(designator) = self.eci {
+            // Spec: 8.4, Table 2 gives 0111 as the ECI mode indicator
+            insert_into_data(&mut bitstream, 0b0111 << 12, 4);
+            for byte in eci_designator_bytes(designator) {
+                insert_into_data(&mut bitstream, (byte as u16) << 8, 8);
+            }
+        }
+        for segment in &self.segments {
+            let (encoding, char_count_value) = (segment.encoding, segment.character_count);
+            let char_count_size = encoding.character_count_bits(version.num);
+            // the indicator would silently lose its high bits, so the count would come out wrong
+            if char_count_value >= 1 << char_count_size {
+                return Err(format!(
+                    "{} characters is too many for the {} bit character count of {:?} mode at version {}",
+                    char_count_value, char_count_size, encoding, version.num
+                )
+                .into());
+            }
+            bitstream.append(&mut encoding.mode());
+            insert_into_data(
+                &mut bitstream,
+                (char_count_value as u16) << (16 - char_count_size),
+                char_count_size,
+            );
+            bitstream.append(&mut segment.data.clone());
+        }
+
+        let doesnt_fit = |length: usize| -> Error {
+            format!(
+                "The data length of {} doesn't fit into the chosen version of {}!",
+                length, version.num
+            )
+            .into()
+        };
 
         // Add the terminator of up to 4 zeroes
-        let remaining_size = codeword_count * 8 - bitstream.len();
+        let remaining_size = (codeword_count * 8)
+            .checked_sub(bitstream.len())
+            .ok_or_else(|| doesnt_fit(bitstream.len()))?;
         for _ in 0..(min(4, remaining_size)) {
             bitstream.push(false);
         }
@@ -276,12 +688,7 @@ impl QRBitstreamEncoder {
 
         // Make sure we haven't somehow gone over (if that happened, there's a bug somewhere!)
         if bitstream.len() / 8 > codeword_count {
-            return Err(format!(
-                "The data length of {} doesn't fit into the chosen version of {}!",
-                bitstream.len(),
-                version.num
-            )
-            .into());
+            return Err(doesnt_fit(bitstream.len()));
         }
 
         // Pad remaining codewords with a cycle of 0xEC and 0x11
@@ -293,19 +700,48 @@ impl QRBitstreamEncoder {
         Ok(bitstream)
     }
 
+    /// Returns the data codewords in the order the spec requires (section 8.7): the first bit of
+    /// the bitstream is the most significant bit of the first codeword. This is the same as
+    /// `codewords_msb_first`.
     pub fn codewords(
         &mut self,
         version: &Version,
         ecl: &ErrorCorrectionLevel,
     ) -> Result<Vec<u8>, Error> {
-        let bitstream = self.bitstream(&version, &ecl)?;
+        self.codewords_msb_first(version, ecl)
+    }
+
+    /// Returns the data codewords with the first bit of each one in its most significant bit, as
+    /// the spec requires.
+    pub fn codewords_msb_first(
+        &mut self,
+        version: &Version,
+        ecl: &ErrorCorrectionLevel,
+    ) -> Result<Vec<u8>, Error> {
+        // Our bitstream stores the first bit in the least significant bit of each byte, so we
+        // have to reverse each individual byte to get them to come out right
+        Ok(self
+            .codewords_lsb_first(version, ecl)?
+            .into_iter()
+            .map(u8::reverse_bits)
+            .collect())
+    }
+
+    /// Returns the data codewords with the first bit of each one in its least significant bit,
+    /// which is how they're stored internally. This is *not* the order the spec uses; it's only
+    /// here for interoperating with code that expects it.
+    pub fn codewords_lsb_first(
+        &mut self,
+        version: &Version,
+        ecl: &ErrorCorrectionLevel,
+    ) -> Result<Vec<u8>, Error> {
+        let bitstream = self.bitstream(version, ecl)?;
         if bitstream.len() % 8 != 0 {
             Err("The bitstream didn't come out in even bytes!".into())
-        } else if bitstream.len() / 8 != version.codeword_count(&ecl) {
+        } else if bitstream.len() / 8 != version.codeword_count(ecl) {
             Err("The bitstream has the wrong number of codewords for the version!".into())
         } else {
-            // We have to reverse each individual byte to get them to come out right
-            Ok(bitstream.domain().map(|byte| byte.reverse_bits()).collect())
+            Ok(bitstream.domain().collect())
         }
     }
 }
@@ -314,6 +750,13 @@ impl QRBitstreamEncoder {
 mod tests {
     use super::*;
 
+    fn segment(mode: QREncoding, data: &str) -> Segment {
+        Segment {
+            mode,
+            data: data.to_owned(),
+        }
+    }
+
     #[test]
     fn test_choose_encoding() {
         assert_eq!(choose_encoding("0051023159"), Numeric);
@@ -323,14 +766,78 @@ mod tests {
         assert_eq!(choose_encoding("Привет, мир!"), Bytes);
     }
 
+    #[test]
+    fn test_choose_encoding_for_version() {
+        for data in &["0051023159", "HELLO WORLD", "hello", "Привет", "1", ""] {
+            for version_num in &[1, 9, 10, 26, 27, 40] {
+                assert_eq!(
+                    choose_encoding_for_version(data, *version_num),
+                    vec![segment(choose_encoding(data), data)]
+                );
+            }
+        }
+
+        // switching to Numeric mode for the digits and back saves 2 bits while the count
+        // indicators are 10 and 9 bits long, but costs 2 more once they grow to 12 and 11
+        let data = "A12345678901234A";
+        assert_eq!(
+            choose_encoding_for_version(data, 9),
+            vec![
+                segment(Alphanumeric, "A"),
+                segment(Numeric, "12345678901234"),
+                segment(Alphanumeric, "A"),
+            ]
+        );
+        assert_eq!(
+            choose_encoding_for_version(data, 10),
+            vec![segment(Alphanumeric, data)]
+        );
+        // each choice is strictly shorter than the other at its version
+        let length = |segments, version_num| {
+            QRBitstreamEncoder::from_segments(segments)
+                .unwrap()
+                .bitstream_length_before_terminator(version_num)
+        };
+        let split = choose_encoding_for_version(data, 9);
+        let single = choose_encoding_for_version(data, 10);
+        assert_eq!(
+            (length(split.clone(), 9), length(single.clone(), 9)),
+            (99, 101)
+        );
+        assert_eq!((length(split, 10), length(single, 10)), (105, 103));
+
+        assert_eq!(
+            choose_encoding_for_version("Invoice 2024000123", 1),
+            vec![segment(Bytes, "Invoice "), segment(Numeric, "2024000123")]
+        );
+    }
+
+    #[test]
+    fn test_character_sixths() {
+        assert_eq!(Numeric.character_sixths('7', true), Some(20));
+        assert_eq!(Numeric.character_sixths('A', true), None);
+        assert_eq!(Alphanumeric.character_sixths('A', true), Some(33));
+        assert_eq!(Alphanumeric.character_sixths('a', true), None);
+        assert_eq!(Bytes.character_sixths('é', true), Some(48));
+        assert_eq!(Bytes.character_sixths('é', false), Some(96));
+        assert_eq!(Kanji.character_sixths('日', false), None);
+    }
+
     mod numeric {
         use super::*;
 
+        #[test]
+        fn test_encode_single_digit() {
+            // a lone digit takes 4 bits
+            let encoded = Numeric.encode("7", ByteCharset::Auto).unwrap();
+            assert_eq!(encoded, bitvec![0, 1, 1, 1]);
+        }
+
         #[test]
         fn test_encode_numeric() {
             let data = "12300001010";
-            let encoding = choose_encoding(&data);
-            let encoded = encoding.encode(&data);
+            let encoding = choose_encoding(data);
+            let encoded = encoding.encode(data, ByteCharset::Auto).unwrap();
             assert_eq!(encoded.len(), 37);
             assert_eq!(
                 encoded,
@@ -340,16 +847,52 @@ mod tests {
                 ]
             );
         }
+
+        fn digits(count: usize) -> String {
+            (0..count)
+                .map(|i| char::from(b'0' + (i * 7 % 10) as u8))
+                .collect()
+        }
+
+        #[test]
+        fn test_encode_100_digits() {
+            let data = digits(100);
+            // 33 groups of three digits, then a single digit
+            assert_eq!(encode_numeric(&data).len(), 33 * 10 + 4);
+            for length in (0..10).chain([100]) {
+                let encoded = encode_numeric(&data[..length]);
+                // read each group of digits back out of the bits
+                let mut bits = encoded.iter().map(|bit| *bit as u32);
+                let mut decoded = String::new();
+                for group in data.as_bytes()[..length].chunks(3) {
+                    let bitcount = [0, 4, 7, 10][group.len()];
+                    let value = bits
+                        .by_ref()
+                        .take(bitcount)
+                        .fold(0, |acc, bit| acc << 1 | bit);
+                    decoded += &format!("{:0width$}", value, width = group.len());
+                }
+                assert_eq!(bits.next(), None);
+                assert_eq!(decoded, data[..length]);
+            }
+        }
     }
 
     mod alphanumeric {
         use super::*;
 
+        #[test]
+        fn test_encode_single_character() {
+            // a lone character takes 6 bits; A is 10
+            let encoded = Alphanumeric.encode("A", ByteCharset::Auto).unwrap();
+            assert_eq!(encoded, bitvec![0, 0, 1, 0, 1, 0]);
+        }
+
         #[test]
         fn test_encode_hello_world() {
             let data = "HELLO WORLD";
-            let encoding = choose_encoding(&data);
-            let encoded = encoding.encode(&data);
+            let encoding = choose_encoding(data);
+            let encoded = encoding.encode(data, ByteCharset::Auto).unwrap();
             assert_eq!(encoded.len(), 61);
             assert_eq!(
                 encoded,
@@ -368,9 +911,9 @@ mod tests {
         #[test]
         fn test_encode_byte_iso8859() {
             let data = "Hello, world!";
-            let encoding = choose_encoding(&data);
+            let encoding = choose_encoding(data);
             assert_eq!(
-                encoding.encode(&data),
+                encoding.encode(data, ByteCharset::Auto).unwrap(),
                 bytes_to_bitvec(vec![
                     0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64, 0x21
                 ])
@@ -380,9 +923,9 @@ mod tests {
         #[test]
         fn test_encode_byte_utf8() {
             let data = "Привет, мир!";
-            let encoding = choose_encoding(&data);
+            let encoding = choose_encoding(data);
             assert_eq!(
-                encoding.encode(&data),
+                encoding.encode(data, ByteCharset::Auto).unwrap(),
                 bytes_to_bitvec(vec![
                     208, 159, 209, 128, 208, 184, 208, 178, 208, 181, 209, 130, 44, 32, 208, 188,
                     208, 184, 209, 128, 33
@@ -391,11 +934,250 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_structured_append_header() {
+        let header = StructuredAppend {
+            index: 2,
+            total: 4,
+            parity: 0xA5,
+        };
+        let version = Version::by_num(1);
+        let ecl = ErrorCorrectionLevel::Low;
+        let plain = QRBitstreamEncoder::new("AB");
+        let mut appended = QRBitstreamEncoder::new("AB").with_structured_append(header);
+        assert_eq!(
+            appended.codeword_count_before_padding(1),
+            (STRUCTURED_APPEND_HEADER_BITS + 4 + 9 + 11).div_ceil(8)
+        );
+        assert!(plain.codeword_count_before_padding(1) < appended.codeword_count_before_padding(1));
+        let codewords = appended.codewords(version, &ecl).unwrap();
+        // 0011, then position 2 and total 4 (written as 3), then the parity, then Alphanumeric
+        assert_eq!(codewords[..3], [0b0011_0010, 0b0011_1010, 0b0101_0010]);
+    }
+
+    #[test]
+    fn test_eci_header() {
+        assert_eq!(eci_designator_bytes(26), [26]);
+        assert_eq!(eci_designator_bytes(1000), [0x83, 0xE8]);
+        assert_eq!(eci_designator_bytes(999_999), [0xCF, 0x42, 0x3F]);
+
+        let version = Version::by_num(1);
+        let ecl = ErrorCorrectionLevel::Low;
+        let mut encoder = QRBitstreamEncoder::from_bytes(b"A").with_eci(26).unwrap();
+        assert_eq!(encoder.codeword_count_before_padding(1), 4);
+        let codewords = encoder.codewords(version, &ecl).unwrap();
+        // 0111, designator 26, then Bytes mode with a count of 1 and 'A'
+        assert_eq!(
+            codewords[..4],
+            [0b0111_0001, 0b1010_0100, 0b0000_0001, 0b0100_0001]
+        );
+        assert!(QRBitstreamEncoder::new("A").with_eci(1_000_000).is_err());
+    }
+
+    #[test]
+    fn test_from_segments() {
+        let segments = vec![
+            Segment {
+                mode: Alphanumeric,
+                data: "AB".to_owned(),
+            },
+            Segment {
+                mode: Numeric,
+                data: "1".to_owned(),
+            },
+        ];
+        let mut encoder = QRBitstreamEncoder::from_segments(segments).unwrap();
+        // 4 + 9 + 11 bits of Alphanumeric "AB", then 4 + 10 + 4 bits of Numeric "1"
+        assert_eq!(
+            encoder.codeword_count_before_padding(1),
+            42_usize.div_ceil(8)
+        );
+        assert_eq!(encoder.character_count(), 3);
+        let modes: Vec<(QREncoding, usize)> = encoder
+            .segments()
+            .iter()
+            .map(|segment| (segment.encoding, segment.character_count))
+            .collect();
+        assert_eq!(modes, [(Alphanumeric, 2), (Numeric, 1)]);
+        let bitstream = encoder
+            .bitstream(Version::by_num(1), &ErrorCorrectionLevel::Low)
+            .unwrap();
+        let expected = "0010 000000010 00111001101 0001 0000000001 0001";
+        let bits: String = bitstream
+            .iter()
+            .take(42)
+            .map(|bit| if *bit { '1' } else { '0' })
+            .collect();
+        assert_eq!(bits, expected.replace(' ', ""));
+    }
+
+    #[test]
+    fn test_from_segments_denser() {
+        let mixed = QRBitstreamEncoder::from_segments(vec![
+            Segment {
+                mode: Bytes,
+                data: "abc".to_owned(),
+            },
+            Segment {
+                mode: Numeric,
+                data: "12345678901234".to_owned(),
+            },
+        ])
+        .unwrap();
+        let single = QRBitstreamEncoder::new("abc12345678901234");
+        assert_eq!(single.encoding(), Bytes);
+        assert!(mixed.codeword_count_before_padding(1) < single.codeword_count_before_padding(1));
+    }
+
+    #[test]
+    fn test_from_segments_invalid() {
+        let error = QRBitstreamEncoder::from_segments(vec![segment(Numeric, "12a")]).unwrap_err();
+        assert!(error.contains("'a'"), "{}", error);
+        assert!(QRBitstreamEncoder::from_segments(vec![segment(Alphanumeric, "abc")]).is_err());
+        assert!(QRBitstreamEncoder::from_segments(vec![segment(Kanji, "")]).is_err());
+        assert!(QRBitstreamEncoder::from_segments(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_with_mode() {
+        let forced = QRBitstreamEncoder::with_mode("12345", Bytes).unwrap();
+        assert_eq!(forced.encoding(), Bytes);
+        assert_eq!(forced.character_count(), 5);
+        assert_eq!(forced.segments()[0].data.len(), 5 * 8);
+        assert_eq!(QRBitstreamEncoder::new("12345").encoding(), Numeric);
+        assert_eq!(
+            QRBitstreamEncoder::with_mode("HELLO", Alphanumeric)
+                .unwrap()
+                .segments()[0]
+                .data,
+            QRBitstreamEncoder::new("HELLO").segments()[0].data
+        );
+
+        let error = QRBitstreamEncoder::with_mode("HELLO world", Alphanumeric).unwrap_err();
+        assert!(error.contains("'w'"), "{}", error);
+        assert!(QRBitstreamEncoder::with_mode("12a", Numeric).is_err());
+        assert!(QRBitstreamEncoder::with_mode("", Kanji).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_too_long() {
+        let mut encoder = QRBitstreamEncoder::from_bytes(&[1u8; 100]);
+        let error = encoder
+            .bitstream(Version::by_num(1), &ErrorCorrectionLevel::Low)
+            .unwrap_err();
+        assert!(error.contains("doesn't fit"), "{}", error);
+
+        let mut encoder = QRBitstreamEncoder::from_bytes(&[1u8; 65_539]);
+        assert_eq!(encoder.character_count(), 65_539);
+        let error = encoder
+            .bitstream(Version::by_num(40), &ErrorCorrectionLevel::Low)
+            .unwrap_err();
+        assert!(error.contains("65539 characters"), "{}", error);
+    }
+
+    #[test]
+    fn test_needs_utf8_eci() {
+        assert!(needs_utf8_eci("日本語", ByteCharset::Auto));
+        assert!(!needs_utf8_eci("hello", ByteCharset::Auto));
+        assert!(!needs_utf8_eci("hello", ByteCharset::Utf8));
+        // Latin-1 covers it, unless UTF-8 is asked for
+        assert!(!needs_utf8_eci("Grüße", ByteCharset::Auto));
+        assert!(needs_utf8_eci("Grüße", ByteCharset::Utf8));
+    }
+
+    #[test]
+    fn test_character_count_overflow() {
+        // version 1 has a 9 bit count in Alphanumeric mode, so at most 511 characters
+        let mut encoder = QRBitstreamEncoder::new(&"A".repeat(600));
+        let error = encoder
+            .bitstream(Version::by_num(1), &ErrorCorrectionLevel::Low)
+            .unwrap_err();
+        assert!(error.contains("9 bit character count"), "{}", error);
+
+        // a count past 16 bits is kept whole, rather than wrapping around to a small one
+        let mut encoder = QRBitstreamEncoder::with_mode(&"a".repeat(65_539), Bytes).unwrap();
+        assert_eq!(encoder.character_count(), 65_539);
+        let error = encoder
+            .bitstream(Version::by_num(40), &ErrorCorrectionLevel::Low)
+            .unwrap_err();
+        assert!(error.contains("65539 characters"), "{}", error);
+    }
+
+    mod byte_charset {
+        use super::*;
+
+        #[test]
+        fn test_forced_charsets() {
+            let utf8 = QRBitstreamEncoder::with_charset("é", ByteCharset::Utf8).unwrap();
+            assert_eq!(utf8.segments()[0].data, bytes_to_bitvec(vec![0xC3, 0xA9]));
+            assert_eq!(utf8.character_count(), 2);
+
+            let latin1 = QRBitstreamEncoder::with_charset("é", ByteCharset::Latin1).unwrap();
+            assert_eq!(latin1.segments()[0].data, bytes_to_bitvec(vec![0xE9]));
+            assert_eq!(latin1.character_count(), 1);
+
+            let auto = QRBitstreamEncoder::with_charset("é", ByteCharset::Auto).unwrap();
+            assert_eq!(auto.segments()[0].data, latin1.segments()[0].data);
+        }
+
+        #[test]
+        fn test_latin1_unrepresentable() {
+            assert!(QRBitstreamEncoder::with_charset("Привет", ByteCharset::Latin1).is_err());
+        }
+
+        #[test]
+        fn test_character_count() {
+            let kanji = "日本語";
+            // three 13-bit Kanji mode characters, against nine UTF-8 bytes
+            let kanji_bits = bitvec![Lsb0, u8; 0; 3 * 13];
+            assert_eq!(Kanji.character_count(kanji, &kanji_bits), 3);
+            let utf8 = QRBitstreamEncoder::new(kanji);
+            assert_eq!(utf8.encoding(), Bytes);
+            assert_eq!(utf8.character_count(), 9);
+            assert_eq!(QRBitstreamEncoder::new("HELLO").character_count(), 5);
+        }
+    }
+
+    mod script_analysis {
+        use super::*;
+
+        #[test]
+        fn test_mixed_scripts() {
+            let report = script_analysis("Приветhello");
+            assert_eq!(report.scripts, vec![Script::Cyrillic, Script::Latin]);
+            assert!(report.is_mixed());
+            assert!(report.requires_bytes());
+        }
+
+        #[test]
+        fn test_single_script() {
+            let report = script_analysis("HELLO WORLD 123");
+            assert_eq!(report.scripts, vec![Script::Latin]);
+            assert!(!report.is_mixed());
+            assert_eq!(report.encoding, Alphanumeric);
+
+            let report = script_analysis("2020-10-31");
+            assert!(report.scripts.is_empty());
+            assert!(!report.requires_bytes());
+        }
+    }
+
     mod encoder {
         use crate::qr::version::Version;
 
         use super::*;
 
+        const COMPLICATED: &str = "Hello, world! I am a weirdly complicated QR code!";
+
+        /// The data codewords of `COMPLICATED` at 5-Q.
+        const COMPLICATED_5Q_CODEWORDS: [u8; 62] = [
+            0x43, 0x14, 0x86, 0x56, 0xC6, 0xC6, 0xF2, 0xC2, 0x07, 0x76, 0xF7, 0x26, 0xC6, 0x42,
+            0x12, 0x04, 0x92, 0x06, 0x16, 0xD2, 0x06, 0x12, 0x07, 0x76, 0x56, 0x97, 0x26, 0x46,
+            0xC7, 0x92, 0x06, 0x36, 0xF6, 0xD7, 0x06, 0xC6, 0x96, 0x36, 0x17, 0x46, 0x56, 0x42,
+            0x05, 0x15, 0x22, 0x06, 0x36, 0xF6, 0x46, 0x52, 0x10, 0xEC, 0x11, 0xEC, 0x11, 0xEC,
+            0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC,
+        ];
+
         #[test]
         fn test_numeric() {
             let mut encoder = QRBitstreamEncoder::new("12300001010");
@@ -524,20 +1306,68 @@ mod tests {
 
         #[test]
         fn test_bytes_into_codewords() {
-            let mut encoder =
-                QRBitstreamEncoder::new("Hello, world! I am a weirdly complicated QR code!");
+            let mut encoder = QRBitstreamEncoder::new(COMPLICATED);
             assert_eq!(
                 encoder
                     .codewords(Version::by_num(5), &ErrorCorrectionLevel::Quartile)
                     .unwrap(),
-                vec![
-                    0x43, 0x14, 0x86, 0x56, 0xC6, 0xC6, 0xF2, 0xC2, 0x07, 0x76, 0xF7, 0x26, 0xC6,
-                    0x42, 0x12, 0x04, 0x92, 0x06, 0x16, 0xD2, 0x06, 0x12, 0x07, 0x76, 0x56, 0x97,
-                    0x26, 0x46, 0xC7, 0x92, 0x06, 0x36, 0xF6, 0xD7, 0x06, 0xC6, 0x96, 0x36, 0x17,
-                    0x46, 0x56, 0x42, 0x05, 0x15, 0x22, 0x06, 0x36, 0xF6, 0x46, 0x52, 0x10, 0xEC,
-                    0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC
-                ],
+                COMPLICATED_5Q_CODEWORDS,
             )
         }
+
+        #[test]
+        fn test_codeword_bit_order() {
+            let version = Version::by_num(5);
+            let ecl = ErrorCorrectionLevel::Quartile;
+            let mut encoder = QRBitstreamEncoder::new(COMPLICATED);
+            let msb_first = encoder.codewords_msb_first(version, &ecl).unwrap();
+            let lsb_first = encoder.codewords_lsb_first(version, &ecl).unwrap();
+
+            assert_eq!(encoder.codewords(version, &ecl).unwrap(), msb_first);
+            // 0100 (bytes mode) 00110001 (49 characters) 0100 1000 ('H')...
+            assert_eq!(msb_first, COMPLICATED_5Q_CODEWORDS);
+            assert_eq!(&lsb_first[..4], &[0xC2, 0x28, 0x61, 0x6A]);
+            assert_eq!(lsb_first, COMPLICATED_5Q_CODEWORDS.map(u8::reverse_bits));
+
+            // the same bit order carries through interleaving the blocks
+            let ecl_data = version.values_at_ecl(&ecl);
+            let with_ec = crate::qr::error_correction::bitstream_with_ec(msb_first, ecl_data)
+                .unwrap()
+                .into_vec();
+            let interleaved: Vec<u8> =
+                crate::qr::error_correction::interleaved_data_order(ecl_data)
+                    .iter()
+                    .map(|&idx| COMPLICATED_5Q_CODEWORDS[idx])
+                    .collect();
+            assert_eq!(
+                with_ec[..interleaved.len()]
+                    .iter()
+                    .map(|byte| byte.reverse_bits())
+                    .collect::<Vec<_>>(),
+                interleaved
+            );
+        }
+
+        #[test]
+        fn test_padding_codeword_count() {
+            let version = Version::by_num(1);
+            // 4 + 10 + 4 data bits, then the terminator: 3 codewords of 19
+            let encoder = QRBitstreamEncoder::new("1");
+            assert_eq!(
+                encoder.padding_codeword_count(version, &ErrorCorrectionLevel::Low),
+                16
+            );
+            // 4 + 9 + 11 data bits fill 3 codewords exactly, so the terminator takes a fourth
+            let mut encoder = QRBitstreamEncoder::new("AB");
+            assert_eq!(
+                encoder.padding_codeword_count(version, &ErrorCorrectionLevel::Low),
+                15
+            );
+            let mut bitstream = encoder
+                .codewords(version, &ErrorCorrectionLevel::Low)
+                .unwrap();
+            assert_eq!(bitstream[3], 0);
+            assert_eq!(bitstream.split_off(4)[..2], [0xEC, 0x11]);
+        }
     }
 }
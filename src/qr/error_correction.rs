@@ -3,7 +3,9 @@ use std::str::FromStr;
 use crate::qr::version::VersionEclData;
 use crate::qr::{bytes_to_bitvec, Error, QREncodedData};
 
-#[derive(Debug)]
+/// Ordered from the least to the most error correction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ErrorCorrectionLevel {
     Low,
     Medium,
@@ -16,12 +18,13 @@ impl FromStr for ErrorCorrectionLevel {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "low" => Ok(ErrorCorrectionLevel::Low),
-            "medium" => Ok(ErrorCorrectionLevel::Medium),
-            "quartile" => Ok(ErrorCorrectionLevel::Quartile),
-            "high" => Ok(ErrorCorrectionLevel::High),
+            // numbered from weakest to strongest, as some other tools do
+            "low" | "0" => Ok(ErrorCorrectionLevel::Low),
+            "medium" | "1" => Ok(ErrorCorrectionLevel::Medium),
+            "quartile" | "2" => Ok(ErrorCorrectionLevel::Quartile),
+            "high" | "3" => Ok(ErrorCorrectionLevel::High),
             _ => Err(format!(
-                "Unknown error correction level {} (options are low, medium, quartile, high)",
+                "Unknown error correction level {} (options are low, medium, quartile, high, or 0-3)",
                 s
             )
             .into()),
@@ -39,7 +42,7 @@ fn gf256_multiply(x: u8, y: u8) -> u8 {
 }
 
 fn generator_polynomial(count: usize) -> Vec<u8> {
-    let mut generator = vec![0; (count - 1) as usize];
+    let mut generator = vec![0; count - 1];
     generator.push(1);
     let mut multiplicand = 1;
     for _ in 0..count {
@@ -66,6 +69,141 @@ fn compute_ec_codewords(block: &[u8], generator: &[u8]) -> Vec<u8> {
     ec_codewords
 }
 
+fn gf256_pow(x: u8, power: usize) -> u8 {
+    let mut out = 1;
+    for _ in 0..(power % 255) {
+        out = gf256_multiply(out, x);
+    }
+    out
+}
+
+fn gf256_inverse(x: u8) -> u8 {
+    gf256_pow(x, 254)
+}
+
+/// Evaluates a polynomial whose coefficients are ordered from the lowest degree up.
+fn poly_eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter()
+        .rev()
+        .fold(0, |acc, &coefficient| gf256_multiply(acc, x) ^ coefficient)
+}
+
+/// Computes the syndromes of a block of data + EC codewords, where the first codeword is the
+/// highest-degree coefficient. All-zero syndromes mean the block is intact.
+fn syndromes(block: &[u8], ec_count: usize) -> Vec<u8> {
+    (0..ec_count)
+        .map(|i| {
+            let x = gf256_pow(2, i);
+            block
+                .iter()
+                .fold(0, |acc, &codeword| gf256_multiply(acc, x) ^ codeword)
+        })
+        .collect()
+}
+
+/// Finds the error locator polynomial with the Berlekamp-Massey algorithm.
+fn error_locator(syndromes: &[u8]) -> Vec<u8> {
+    let mut locator = vec![1u8];
+    let mut previous = vec![1u8];
+    let mut length = 0;
+    let mut shift = 1;
+    let mut previous_discrepancy = 1u8;
+
+    for n in 0..syndromes.len() {
+        let mut discrepancy = syndromes[n];
+        for i in 1..=length {
+            discrepancy ^= gf256_multiply(*locator.get(i).unwrap_or(&0), syndromes[n - i]);
+        }
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        let coefficient = gf256_multiply(discrepancy, gf256_inverse(previous_discrepancy));
+        let mut next = locator.clone();
+        if next.len() < previous.len() + shift {
+            next.resize(previous.len() + shift, 0);
+        }
+        for (i, &value) in previous.iter().enumerate() {
+            next[i + shift] ^= gf256_multiply(coefficient, value);
+        }
+
+        if 2 * length <= n {
+            previous = locator;
+            length = n + 1 - length;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+        locator = next;
+    }
+    locator.truncate(length + 1);
+    locator
+}
+
+/// Corrects errors in a block of data + EC codewords in place, as described in the Reed-Solomon
+/// decoding procedure referenced by section 11.5.2 of the spec. Returns the number of codewords
+/// that were corrected, or an error if there are too many to repair.
+pub(crate) fn correct_errors(block: &mut [u8], ec_count: usize) -> Result<usize, Error> {
+    let block_syndromes = syndromes(block, ec_count);
+    if block_syndromes.iter().all(|&s| s == 0) {
+        return Ok(0);
+    }
+
+    let locator = error_locator(&block_syndromes);
+    let error_count = locator.len() - 1;
+    if error_count * 2 > ec_count {
+        return Err("Too many errors in the block to correct".into());
+    }
+
+    // Omega(x) = S(x) * Lambda(x) mod x^ec_count
+    let mut evaluator = vec![0u8; ec_count];
+    for (i, &s) in block_syndromes.iter().enumerate() {
+        for (j, &l) in locator.iter().enumerate() {
+            if i + j < ec_count {
+                evaluator[i + j] ^= gf256_multiply(s, l);
+            }
+        }
+    }
+
+    // The formal derivative only keeps the odd-degree terms in GF(2^8)
+    let derivative: Vec<u8> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &l)| if i % 2 == 1 { l } else { 0 })
+        .collect();
+
+    let length = block.len();
+    let mut corrected = 0;
+    for (idx, codeword) in block.iter_mut().enumerate() {
+        let location = gf256_pow(2, length - 1 - idx);
+        let location_inverse = gf256_inverse(location);
+        if poly_eval(&locator, location_inverse) != 0 {
+            continue;
+        }
+        let denominator = poly_eval(&derivative, location_inverse);
+        if denominator == 0 {
+            return Err("Could not compute an error magnitude".into());
+        }
+        let magnitude = gf256_multiply(
+            location,
+            gf256_multiply(
+                poly_eval(&evaluator, location_inverse),
+                gf256_inverse(denominator),
+            ),
+        );
+        *codeword ^= magnitude;
+        corrected += 1;
+    }
+
+    if corrected != error_count || syndromes(block, ec_count).iter().any(|&s| s != 0) {
+        return Err("Too many errors in the block to correct".into());
+    }
+    Ok(corrected)
+}
+
 type Block = Vec<u8>;
 type Group = Vec<Block>;
 
@@ -128,16 +266,12 @@ impl GroupedCodewords {
     fn interleaved_data_codewords(&self) -> Vec<u8> {
         let mut data = Vec::with_capacity(self.version_data.data_codewords);
         for idx in 0..self.version_data.max_codewords_per_group() {
-            for block in &self.group1_data {
+            let group2 = self.group2_data.iter().flatten();
+            for block in self.group1_data.iter().chain(group2) {
                 if let Some(codeword) = block.get(idx) {
                     data.push(codeword.to_owned());
                 }
             }
-            if let Some(group2) = &self.group2_data {
-                for block in group2 {
-                    data.push(block[idx]);
-                }
-            }
         }
         data
     }
@@ -162,17 +296,58 @@ impl GroupedCodewords {
         data.append(&mut self.interleaved_ec_codewords());
         data
     }
+}
+
+/// For each data codeword in interleaved order, returns its index in the original, unsplit
+/// sequence of data codewords.
+pub(crate) fn interleaved_data_order(version_data: &VersionEclData) -> Vec<usize> {
+    let group1 = &version_data.group1;
+    let group2 = version_data.group2.as_ref();
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for group in std::iter::once(group1).chain(group2) {
+        for _ in 0..group.blocks {
+            blocks.push(start..(start + group.codewords as usize));
+            start += group.codewords as usize;
+        }
+    }
 
-    fn bitstream(&self) -> QREncodedData {
-        bytes_to_bitvec(self.interleaved_codewords())
+    let mut order = Vec::with_capacity(version_data.data_codewords);
+    for idx in 0..version_data.max_codewords_per_group() {
+        for block in &blocks {
+            if block.start + idx < block.end {
+                order.push(block.start + idx);
+            }
+        }
     }
+    order
 }
 
+/// Splits the data codewords into blocks, computes their EC codewords, and interleaves them all
+/// into the final bitstream. Fails if the codewords in or out don't add up to what the version
+/// holds, which would mean a bug in the encoder or the interleaving.
 pub fn bitstream_with_ec(
     data_codewords: Vec<u8>,
     ecl_data: &'static VersionEclData,
-) -> QREncodedData {
-    GroupedCodewords::new(data_codewords, ecl_data).bitstream()
+) -> Result<QREncodedData, Error> {
+    if data_codewords.len() != ecl_data.data_codewords {
+        return Err(format!(
+            "Got {} data codewords where {} were expected",
+            data_codewords.len(),
+            ecl_data.data_codewords
+        )
+        .into());
+    }
+    let codewords = GroupedCodewords::new(data_codewords, ecl_data).interleaved_codewords();
+    if codewords.len() != ecl_data.total_codewords() {
+        return Err(format!(
+            "Interleaving produced {} codewords where {} were expected",
+            codewords.len(),
+            ecl_data.total_codewords()
+        )
+        .into());
+    }
+    Ok(bytes_to_bitvec(codewords))
 }
 
 #[cfg(test)]
@@ -182,6 +357,15 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_from_str() {
+        assert_eq!("medium".parse(), Ok(ErrorCorrectionLevel::Medium));
+        assert_eq!("0".parse(), Ok(ErrorCorrectionLevel::Low));
+        assert_eq!("3".parse(), Ok(ErrorCorrectionLevel::High));
+        assert!("4".parse::<ErrorCorrectionLevel>().is_err());
+        assert!("-1".parse::<ErrorCorrectionLevel>().is_err());
+    }
+
     mod generator_polynomial {
         use crate::qr::error_correction::generator_polynomial;
 
@@ -213,6 +397,47 @@ mod tests {
         )
     }
 
+    mod correct_errors {
+        use super::*;
+
+        fn hello_world_block() -> Vec<u8> {
+            let mut block = QRBitstreamEncoder::new("HELLO WORLD")
+                .codewords(Version::by_num(1), &ErrorCorrectionLevel::Medium)
+                .unwrap();
+            let mut ec = compute_ec_codewords(&block, &generator_polynomial(10));
+            block.append(&mut ec);
+            block
+        }
+
+        #[test]
+        fn test_intact_block() {
+            let mut block = hello_world_block();
+            assert_eq!(correct_errors(&mut block, 10), Ok(0));
+            assert_eq!(block, hello_world_block());
+        }
+
+        #[test]
+        fn test_correctable_errors() {
+            let mut block = hello_world_block();
+            block[0] ^= 0xFF;
+            block[7] ^= 0x01;
+            block[15] = 0;
+            block[20] ^= 0x5A;
+            block[25] ^= 0x80;
+            assert_eq!(correct_errors(&mut block, 10), Ok(5));
+            assert_eq!(block, hello_world_block());
+        }
+
+        #[test]
+        fn test_too_many_errors() {
+            let mut block = hello_world_block();
+            for codeword in block.iter_mut().take(6) {
+                *codeword ^= 0x33;
+            }
+            assert!(correct_errors(&mut block, 10).is_err());
+        }
+    }
+
     fn no_block2() -> GroupedCodewords {
         GroupedCodewords {
             version_data: Version::by_num(1).values_at_ecl(&ErrorCorrectionLevel::Quartile),
@@ -342,6 +567,44 @@ mod tests {
             )
         }
 
+        #[test]
+        fn test_data_order() {
+            let grouped = block2();
+            let codewords: Vec<u8> = grouped
+                .group1_data
+                .iter()
+                .chain(grouped.group2_data.iter().flatten())
+                .flatten()
+                .copied()
+                .collect();
+            let order = interleaved_data_order(grouped.version_data);
+            assert_eq!(&order[..8], &[0, 15, 30, 46, 1, 16, 31, 47]);
+            assert_eq!(&order[order.len() - 2..], &[45, 61]);
+            let reordered: Vec<u8> = order.iter().map(|&idx| codewords[idx]).collect();
+            assert_eq!(reordered, grouped.interleaved_data_codewords());
+        }
+
+        #[test]
+        fn test_data_ragged_groups() {
+            let mut grouped = block2();
+            // group 2 shorter than group 1: the missing column is skipped instead of panicking
+            for block in grouped.group2_data.as_mut().unwrap() {
+                block.truncate(14);
+            }
+            let data = grouped.interleaved_data_codewords();
+            assert_eq!(data.len(), 2 * 15 + 2 * 14);
+            assert_eq!(&data[..8], &[67, 4, 6, 54, 20, 146, 54, 246]);
+            assert_eq!(&data[data.len() - 4..], &[21, 236, 18, 146]);
+
+            // group 1 blocks of differing lengths are interleaved column by column as well
+            let mut grouped = block2();
+            grouped.group1_data[1].truncate(3);
+            let data = grouped.interleaved_data_codewords();
+            assert_eq!(data.len(), 15 + 3 + 2 * 16);
+            assert_eq!(&data[8..16], &[134, 6, 246, 70, 86, 215, 82, 198]);
+            assert_eq!(&data[data.len() - 3..], &[17, 6, 236]);
+        }
+
         #[test]
         fn test_ec_no_block2() {
             let grouped = no_block2();
@@ -373,7 +636,7 @@ mod tests {
 
             let mut encoder = QRBitstreamEncoder::new("Hello world");
             let data_codewords = encoder.codewords(version, &ecl).unwrap();
-            let bitstream = bitstream_with_ec(data_codewords, version.values_at_ecl(&ecl));
+            let bitstream = bitstream_with_ec(data_codewords, version.values_at_ecl(&ecl)).unwrap();
 
             assert_eq!(
                 bitstream,
@@ -390,4 +653,23 @@ mod tests {
             )
         }
     }
+    #[test]
+    fn test_bitstream_length_v40_high() {
+        let version = Version::by_num(40);
+        let ecl = ErrorCorrectionLevel::High;
+        let ecl_data = version.values_at_ecl(&ecl);
+        assert!(ecl_data.group2.is_some());
+        let data_codewords = QRBitstreamEncoder::new("HELLO WORLD")
+            .codewords(version, &ecl)
+            .unwrap();
+        let bitstream = bitstream_with_ec(data_codewords, ecl_data).unwrap();
+        // Spec: Table 1
+        assert_eq!(bitstream.len(), 3706 * 8);
+    }
+
+    #[test]
+    fn test_bitstream_wrong_data_length() {
+        let ecl_data = Version::by_num(1).values_at_ecl(&ErrorCorrectionLevel::Low);
+        assert!(bitstream_with_ec(vec![0; 18], ecl_data).is_err());
+    }
 }
@@ -1,32 +1,1657 @@
-use crate::qr::pattern::QRCode;
+use crate::qr::pattern::{Module, QRCode};
+use crate::qr::svg::PatternShape;
+use crate::qr::version::Version;
 use crate::qr::Error;
-use image::{Rgb, RgbImage};
+use encoding::all::ISO_8859_1;
+use encoding::{EncoderTrap, Encoding};
+use image::{GrayImage, ImageBuffer, Luma, Pixel, Rgb, RgbImage, Rgba, RgbaImage};
+use std::fmt::Write;
+use std::io;
 use std::path::Path;
 
 const PIXELS_PER_MODULE: u32 = 4;
+pub(crate) const QUIET_ZONE_MODULES: u32 = 4;
 const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
 const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+const RED: Rgb<u8> = Rgb([255, 0, 0]);
+const DARK_GRAY: Rgb<u8> = Rgb([96, 96, 96]);
+const LIGHT_GRAY: Rgb<u8> = Rgb([208, 208, 208]);
+const MIN_MODULE_PIXELS: u32 = 3;
+const MIN_SYMBOL_PIXELS: u32 = 80;
+const MIN_CONTRAST_RATIO: f32 = 4.0;
+/// `validate_colors` asks for more than the warning threshold: as much contrast as WCAG's
+/// strictest level asks of body text, since scanners see codes blurred and unevenly lit.
+const SAFE_CONTRAST_RATIO: f32 = 7.0;
+const MM_PER_INCH: f32 = 25.4;
+/// Most symbols a structured append sheet puts side by side before starting another row.
+const SHEET_COLUMNS: usize = 4;
 
-fn modules_to_buffer(code: &QRCode) -> RgbImage {
-    let side_length = PIXELS_PER_MODULE * code.version.modules_per_side() as u32;
-    let mut img = RgbImage::new(side_length, side_length);
-    for (x, row) in code.rows.iter().enumerate() {
-        for (y, module) in row.iter().enumerate() {
-            img.put_pixel(
-                x as u32,
-                y as u32,
-                if module.black() { BLACK } else { WHITE },
+/// Options for the raster backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    /// Width of a module in pixels.
+    pub module_width: u32,
+    /// Height of a module in pixels. Setting this differently from `module_width` is meant for
+    /// displays with non-square pixels; non-square modules can keep cameras from scanning the code.
+    pub module_height: u32,
+    /// Rounds the module width and height up to odd numbers, so every module has a center pixel
+    /// even when a low resolution scan samples it.
+    pub ensure_odd_module_size: bool,
+    /// Pixels of background left between modules, for stylized grid codes; each module is drawn
+    /// that much narrower and shorter, centered in its cell. Finder patterns stay solid, since
+    /// gaps in them throw off the proportions scanners look for.
+    pub module_gutter: u32,
+    /// Width of the light border around the symbol, in modules. The spec requires at least 4.
+    pub quiet_zone: u32,
+    /// Extra light modules around the quiet zone, for printed codes that get trimmed: cutting
+    /// into the bleed leaves the quiet zone itself intact.
+    pub bleed_modules: u32,
+    pub foreground: Rgb<u8>,
+    pub background: Rgb<u8>,
+    /// Opacity of the dark modules in `render_rgba`, from 0 (transparent) to 255 (opaque).
+    pub foreground_alpha: u8,
+    /// Opacity of the light modules and quiet zone in `render_rgba`.
+    pub background_alpha: u8,
+    /// Key/value pairs written to tEXt chunks when saving as PNG. Both must be representable in
+    /// ISO-8859-1, and keys must be 1 to 79 characters long.
+    pub png_metadata: Vec<(String, String)>,
+    /// A frame drawn around the outside of the quiet zone.
+    pub border: Option<Border>,
+    /// A shadow cast down and to the right of the code, outside any border.
+    pub shadow: Option<Shadow>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Border {
+    /// Thickness in pixels.
+    pub width: u32,
+    pub color: Rgb<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shadow {
+    /// How far the shadow is shifted right and down, in pixels.
+    pub offset: u32,
+    pub color: Rgb<u8>,
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Shadow {
+            offset: 4,
+            color: Rgb([160, 160, 160]),
+        }
+    }
+}
+
+/// A reason a rendered code may be hard to scan.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// The quiet zone is narrower than the 4 modules the spec requires.
+    QuietZoneTooSmall { modules: u32 },
+    /// Modules this small are easily blurred together by cameras.
+    ModuleTooSmall { pixels: u32 },
+    /// The whole symbol is too small to be picked out at typical scanning distances.
+    SymbolTooSmall { pixels: u32 },
+    /// The foreground and background are too close in brightness.
+    LowContrast { ratio: f32 },
+    /// The foreground is lighter than the background, which many scanners don't handle.
+    Inverted,
+    /// The dark modules are partly transparent, so whatever is behind them lowers the contrast.
+    TranslucentForeground { alpha: u8 },
+}
+
+/// Relative luminance, as defined by WCAG 2.
+fn luminance(color: Rgb<u8>) -> f32 {
+    let channel = |value: u8| {
+        let value = value as f32 / 255.0;
+        if value <= 0.03928 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(color[0]) + 0.7152 * channel(color[1]) + 0.0722 * channel(color[2])
+}
+
+/// Contrast ratio between two colors, as defined by WCAG 2: from 1 for the same color to 21 for
+/// black and white.
+fn contrast_ratio(a: Rgb<u8>, b: Rgb<u8>) -> f32 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            module_width: PIXELS_PER_MODULE,
+            module_height: PIXELS_PER_MODULE,
+            ensure_odd_module_size: false,
+            module_gutter: 0,
+            quiet_zone: QUIET_ZONE_MODULES,
+            bleed_modules: 0,
+            foreground: BLACK,
+            background: WHITE,
+            foreground_alpha: 255,
+            background_alpha: 255,
+            png_metadata: Vec::new(),
+            border: None,
+            shadow: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub(crate) fn square(module_size: u32) -> Self {
+        RenderOptions {
+            module_width: module_size,
+            module_height: module_size,
+            ..RenderOptions::default()
+        }
+    }
+
+    /// The smallest module size in pixels that prints at least `min_module_mm` wide at the given
+    /// resolution. Scanning guidelines often ask for modules of 0.5mm or more.
+    pub fn module_size_for_physical(min_module_mm: f32, dpi: u32) -> u32 {
+        (min_module_mm / MM_PER_INCH * dpi as f32).ceil().max(1.0) as u32
+    }
+
+    /// The light margin around the symbol that's rendered, in modules: the quiet zone and bleed.
+    pub fn effective_quiet_zone(&self) -> u32 {
+        self.quiet_zone + self.bleed_modules
+    }
+
+    /// The module width and height that will actually be rendered, in pixels.
+    pub fn effective_module_size(&self) -> (u32, u32) {
+        if self.ensure_odd_module_size {
+            (self.module_width | 1, self.module_height | 1)
+        } else {
+            (self.module_width, self.module_height)
+        }
+    }
+
+    /// The size in pixels of the image a code of this version renders to, including the quiet
+    /// zone, border and shadow.
+    pub fn image_dimensions(&self, version: &Version) -> (u32, u32) {
+        let (width, height) = self.effective_module_size();
+        let modules = version.modules_per_side() as u32 + 2 * self.effective_quiet_zone();
+        let border = self.border.as_ref().map_or(0, |border| 2 * border.width);
+        let shadow = self.shadow.as_ref().map_or(0, |shadow| shadow.offset);
+        (
+            width * modules + border + shadow,
+            height * modules + border + shadow,
+        )
+    }
+
+    /// Fails if the foreground and background don't contrast enough to scan reliably, with a
+    /// contrast ratio of at least 7. This is stricter than the low contrast warning from
+    /// `check_scannability`, for checking stylized colors before generating codes with them.
+    pub fn validate_colors(&self) -> Result<(), Error> {
+        let ratio = contrast_ratio(self.foreground, self.background);
+        if ratio < SAFE_CONTRAST_RATIO {
+            return Err(format!(
+                "The colors have a contrast ratio of {:.1}, less than the {} needed to scan reliably",
+                ratio, SAFE_CONTRAST_RATIO
             )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Checks for settings that are likely to make a code of this version hard to scan.
+    pub fn check_scannability(&self, version: &Version) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        if self.quiet_zone < QUIET_ZONE_MODULES {
+            warnings.push(Warning::QuietZoneTooSmall {
+                modules: self.quiet_zone,
+            });
+        }
+        let (width, height) = self.effective_module_size();
+        let module_size = width.min(height);
+        if module_size < MIN_MODULE_PIXELS {
+            warnings.push(Warning::ModuleTooSmall {
+                pixels: module_size,
+            });
+        }
+        let symbol_size = module_size * version.modules_per_side() as u32;
+        if symbol_size < MIN_SYMBOL_PIXELS {
+            warnings.push(Warning::SymbolTooSmall {
+                pixels: symbol_size,
+            });
+        }
+        let ratio = contrast_ratio(self.foreground, self.background);
+        if ratio < MIN_CONTRAST_RATIO {
+            warnings.push(Warning::LowContrast { ratio });
+        }
+        if luminance(self.foreground) > luminance(self.background) {
+            warnings.push(Warning::Inverted);
+        }
+        if self.foreground_alpha < 255 {
+            warnings.push(Warning::TranslucentForeground {
+                alpha: self.foreground_alpha,
+            });
+        }
+        warnings
+    }
+}
+
+/// Colors and shapes for the dark modules of each kind of pattern. Light modules always take the
+/// background color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub foreground: Rgb<u8>,
+    pub background: Rgb<u8>,
+    pub finder: Rgb<u8>,
+    pub finder_shape: PatternShape,
+    pub alignment: Rgb<u8>,
+    pub alignment_shape: PatternShape,
+    pub timing: Rgb<u8>,
+}
+
+impl Theme {
+    /// Plain black on white squares.
+    pub fn classic() -> Self {
+        Theme::monochrome(BLACK, WHITE)
+    }
+
+    /// Square modules all in the same foreground color.
+    pub fn monochrome(foreground: Rgb<u8>, background: Rgb<u8>) -> Self {
+        Theme {
+            foreground,
+            background,
+            finder: foreground,
+            finder_shape: PatternShape::Square,
+            alignment: foreground,
+            alignment_shape: PatternShape::Square,
+            timing: foreground,
+        }
+    }
+
+    /// Navy data with blue finders and round teal alignment patterns on a pale blue background.
+    pub fn ocean() -> Self {
+        Theme {
+            foreground: Rgb([11, 37, 69]),
+            background: Rgb([240, 248, 255]),
+            finder: Rgb([19, 78, 148]),
+            finder_shape: PatternShape::Square,
+            alignment: Rgb([0, 109, 119]),
+            alignment_shape: PatternShape::Circle,
+            timing: Rgb([11, 37, 69]),
+        }
+    }
+
+    /// Dark brown data with deep red finders on a warm cream background.
+    pub fn sunset() -> Self {
+        Theme {
+            foreground: Rgb([61, 28, 2]),
+            background: Rgb([255, 244, 230]),
+            finder: Rgb([153, 27, 27]),
+            finder_shape: PatternShape::Circle,
+            alignment: Rgb([153, 27, 27]),
+            alignment_shape: PatternShape::Square,
+            timing: Rgb([61, 28, 2]),
+        }
+    }
+
+    fn style(&self, module: &Module) -> (Rgb<u8>, PatternShape) {
+        match module {
+            Module::Finder(_) => (self.finder, self.finder_shape),
+            Module::Alignment(_) => (self.alignment, self.alignment_shape),
+            Module::TimingHorizontal(_) | Module::TimingVertical(_) => {
+                (self.timing, PatternShape::Square)
+            }
+            _ => (self.foreground, PatternShape::Square),
+        }
+    }
+}
+
+/// Whether the pixel falls inside the ellipse filling a `width` by `height` module.
+fn inside_circle((px, py): (u32, u32), width: u32, height: u32) -> bool {
+    let dx = (px as f32 + 0.5) / width as f32 - 0.5;
+    let dy = (py as f32 + 0.5) / height as f32 - 0.5;
+    dx * dx + dy * dy <= 0.25
+}
+
+/// Renders the code in the options' colors, including the quiet zone.
+pub fn render(code: &QRCode, options: &RenderOptions) -> RgbImage {
+    render_themed(
+        code,
+        &Theme::monochrome(options.foreground, options.background),
+        options,
+    )
+}
+
+/// Renders the code like `render`, with the foreground and background opacity from the options,
+/// for overlaying on other images. Any border and shadow stay opaque.
+pub fn render_rgba(code: &QRCode, options: &RenderOptions) -> RgbaImage {
+    let with_alpha = |color: Rgb<u8>, alpha| Rgba([color[0], color[1], color[2], alpha]);
+    let theme = Theme::monochrome(options.foreground, options.background);
+    // the symbol only has the two colors, so the decorations can't be mistaken for either
+    let symbol = render_symbol(code, &theme, options);
+    let mut rgba = RgbaImage::new(symbol.width(), symbol.height());
+    for (x, y, &color) in symbol.enumerate_pixels() {
+        let alpha = if color == options.foreground {
+            options.foreground_alpha
+        } else {
+            options.background_alpha
+        };
+        rgba.put_pixel(x, y, with_alpha(color, alpha));
+    }
+    decorate_with(
+        rgba,
+        options,
+        |color| with_alpha(color, 255),
+        with_alpha(options.background, options.background_alpha),
+    )
+}
+
+/// Renders the code with each kind of function pattern colored and shaped by the theme.
+pub fn render_themed(code: &QRCode, theme: &Theme, options: &RenderOptions) -> RgbImage {
+    decorate(render_symbol(code, theme, options), options)
+}
+
+/// Adds the border and shadow from the options around an image of the code and its quiet zone.
+fn decorate(img: RgbImage, options: &RenderOptions) -> RgbImage {
+    decorate_with(img, options, |color| color, options.background)
+}
+
+/// Like `decorate`, for any kind of pixel: `pixel` converts the border and shadow colors, and
+/// `background` fills the space the shadow's offset leaves uncovered.
+fn decorate_with<P: Pixel<Subpixel = u8> + 'static>(
+    img: ImageBuffer<P, Vec<u8>>,
+    options: &RenderOptions,
+    pixel: impl Fn(Rgb<u8>) -> P,
+    background: P,
+) -> ImageBuffer<P, Vec<u8>> {
+    let img = match &options.border {
+        Some(border) => {
+            let (width, height) = img.dimensions();
+            let mut framed = ImageBuffer::from_pixel(
+                width + 2 * border.width,
+                height + 2 * border.width,
+                pixel(border.color),
+            );
+            image::imageops::replace(&mut framed, &img, border.width, border.width);
+            framed
+        }
+        None => img,
+    };
+    match &options.shadow {
+        Some(shadow) => {
+            let (width, height) = img.dimensions();
+            let mut shadowed =
+                ImageBuffer::from_pixel(width + shadow.offset, height + shadow.offset, background);
+            let cast = ImageBuffer::from_pixel(width, height, pixel(shadow.color));
+            image::imageops::replace(&mut shadowed, &cast, shadow.offset, shadow.offset);
+            image::imageops::replace(&mut shadowed, &img, 0, 0);
+            shadowed
+        }
+        None => img,
+    }
+}
+
+fn render_symbol(code: &QRCode, theme: &Theme, options: &RenderOptions) -> RgbImage {
+    let (width, height) = options.effective_module_size();
+    let quiet_zone = options.effective_quiet_zone();
+    let modules = code.version.modules_per_side() as u32 + 2 * quiet_zone;
+    let mut img = RgbImage::from_pixel(width * modules, height * modules, theme.background);
+    for ((x, y), module) in code.modules() {
+        if !module.black() {
+            continue;
+        }
+        let (color, shape) = theme.style(module);
+        let left = (x as u32 + quiet_zone) * width;
+        let top = (y as u32 + quiet_zone) * height;
+        let gutter = match module {
+            Module::Finder(_) => 0,
+            _ => options.module_gutter,
+        };
+        // the gutter is split between the two sides, with any odd pixel on the right or bottom
+        let (drawn_width, drawn_height) =
+            (width.saturating_sub(gutter), height.saturating_sub(gutter));
+        let (inset_x, inset_y) = ((width - drawn_width) / 2, (height - drawn_height) / 2);
+        for px in 0..drawn_width {
+            for py in 0..drawn_height {
+                if shape == PatternShape::Square
+                    || inside_circle((px, py), drawn_width, drawn_height)
+                {
+                    img.put_pixel(left + inset_x + px, top + inset_y + py, color);
+                }
+            }
+        }
+    }
+    img
+}
+
+/// Alpha masks of a code's black modules, split by what they belong to, so each part can be
+/// styled separately in an image editor or compositor. Each mask is 255 over black modules of
+/// its kind and 0 elsewhere, and is the size of the code with its quiet zone; borders and shadows
+/// aren't included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layers {
+    /// The three finder patterns.
+    pub finders: GrayImage,
+    pub alignment: GrayImage,
+    /// Data, timing, format and version modules.
+    pub data: GrayImage,
+}
+
+/// Renders the code's black modules as separate layers for finder patterns, alignment patterns
+/// and everything else, using the module size and quiet zone from the options.
+pub fn render_layers(code: &QRCode, options: &RenderOptions) -> Layers {
+    let (width, height) = options.effective_module_size();
+    let quiet_zone = options.effective_quiet_zone();
+    let modules = code.version.modules_per_side() as u32 + 2 * quiet_zone;
+    let blank = GrayImage::new(width * modules, height * modules);
+    let mut layers = Layers {
+        finders: blank.clone(),
+        alignment: blank.clone(),
+        data: blank,
+    };
+    for ((x, y), module) in code.modules() {
+        if !module.black() {
+            continue;
+        }
+        let layer = match module {
+            Module::Finder(_) => &mut layers.finders,
+            Module::Alignment(_) => &mut layers.alignment,
+            _ => &mut layers.data,
+        };
+        let left = (x as u32 + quiet_zone) * width;
+        let top = (y as u32 + quiet_zone) * height;
+        for px in 0..width {
+            for py in 0..height {
+                layer.put_pixel(left + px, top + py, Luma([255]));
+            }
+        }
+    }
+    layers
+}
+
+/// Renders the code centered on a canvas of the given size, e.g. for posting on social media.
+/// The canvas is filled with `background` outside the rendered image, which still has its own
+/// quiet zone in the options' background color. Fails if the image doesn't fit.
+pub fn on_canvas(
+    code: &QRCode,
+    (width, height): (u32, u32),
+    background: Rgb<u8>,
+    options: &RenderOptions,
+) -> Result<RgbImage, Error> {
+    let (code_width, code_height) = options.image_dimensions(code.version);
+    if code_width > width || code_height > height {
+        return Err(format!(
+            "The code is {}x{} pixels, which doesn't fit on a {}x{} canvas",
+            code_width, code_height, width, height
+        )
+        .into());
+    }
+    let mut canvas = RgbImage::from_pixel(width, height, background);
+    image::imageops::replace(
+        &mut canvas,
+        &render(code, options),
+        (width - code_width) / 2,
+        (height - code_height) / 2,
+    );
+    Ok(canvas)
+}
+
+/// Renders the code as a packed 1-bit bitmap for thermal printers, `target_width_dots` wide.
+/// Modules are scaled by the largest whole number of dots that fits the code and its quiet zone
+/// across the width, and the code is centered horizontally. A set bit prints a dot, the first
+/// dot of each row is the most significant bit of its first byte, and each row is padded out to
+/// a whole number of bytes. Fails if the printer is too narrow for a dot per module.
+pub fn to_thermal_bitmap(code: &QRCode, target_width_dots: u32) -> Result<Vec<u8>, Error> {
+    let side = code.version.modules_per_side() as u32;
+    let modules = side + 2 * QUIET_ZONE_MODULES;
+    let scale = target_width_dots / modules;
+    if scale == 0 {
+        return Err(format!(
+            "The code needs {} dots across, but the printer only has {}",
+            modules, target_width_dots
+        )
+        .into());
+    }
+    let row_bytes = target_width_dots.div_ceil(8) as usize;
+    let height = modules * scale;
+    let left = (target_width_dots - modules * scale) / 2 + QUIET_ZONE_MODULES * scale;
+    let top = QUIET_ZONE_MODULES * scale;
+    let mut bitmap = vec![0u8; row_bytes * height as usize];
+    for ((x, y), module) in code.modules() {
+        if !module.black() {
+            continue;
+        }
+        for dy in 0..scale {
+            let row = (top + y as u32 * scale + dy) as usize * row_bytes;
+            for dx in 0..scale {
+                let dot = left + x as u32 * scale + dx;
+                bitmap[row + dot as usize / 8] |= 0x80 >> (dot % 8);
+            }
+        }
+    }
+    Ok(bitmap)
+}
+
+/// A rectangle of modules, with its top left corner at `x`, `y`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModuleRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Renders the code with the colors of every module inside the frame swapped, e.g. to make room
+/// for a logo. The inverted modules read as errors, so this needs a high enough ECL to still
+/// scan. Fails if the frame leaves the symbol or covers part of a finder pattern.
+pub fn with_inverted_frame(
+    code: &QRCode,
+    frame: ModuleRect,
+    options: &RenderOptions,
+) -> Result<RgbImage, Error> {
+    let side = code.version.modules_per_side();
+    if frame.x + frame.width > side || frame.y + frame.height > side {
+        return Err("The frame doesn't fit inside the code".into());
+    }
+    let columns = frame.x..(frame.x + frame.width);
+    let rows = frame.y..(frame.y + frame.height);
+    let covers_finder = code.modules().any(|((x, y), module)| {
+        columns.contains(&x) && rows.contains(&y) && matches!(module, Module::Finder(_))
+    });
+    if covers_finder {
+        return Err("The frame can't overlap a finder pattern".into());
+    }
+
+    let theme = Theme::monochrome(options.foreground, options.background);
+    let mut img = render_symbol(code, &theme, options);
+    let (width, height) = options.effective_module_size();
+    for x in columns {
+        for y in rows.clone() {
+            let color = if code.module((x, y)).black() {
+                options.background
+            } else {
+                options.foreground
+            };
+            let left = (x as u32 + options.effective_quiet_zone()) * width;
+            let top = (y as u32 + options.effective_quiet_zone()) * height;
+            for px in left..(left + width) {
+                for py in top..(top + height) {
+                    img.put_pixel(px, py, color);
+                }
+            }
+        }
+    }
+    Ok(decorate(img, options))
+}
+
+/// Renders where two codes of the same version differ: modules that match are dark or light gray
+/// depending on their color, and modules that differ are red. The module size and quiet zone
+/// come from the options; colors, border and shadow are ignored.
+pub fn diff(a: &QRCode, b: &QRCode, options: &RenderOptions) -> Result<RgbImage, Error> {
+    if a.version.num != b.version.num {
+        return Err(format!(
+            "Can't compare a version {} code with a version {} code",
+            a.version.num, b.version.num
+        )
+        .into());
+    }
+    let (width, height) = options.effective_module_size();
+    let quiet_zone = options.effective_quiet_zone();
+    let modules = a.version.modules_per_side() as u32 + 2 * quiet_zone;
+    let mut img = RgbImage::from_pixel(width * modules, height * modules, WHITE);
+    for ((x, y), module) in a.modules() {
+        let color = match (module.black(), b.module((x, y)).black()) {
+            (true, true) => DARK_GRAY,
+            (false, false) => LIGHT_GRAY,
+            _ => RED,
+        };
+        let left = (x as u32 + quiet_zone) * width;
+        let top = (y as u32 + quiet_zone) * height;
+        for px in 0..width {
+            for py in 0..height {
+                img.put_pixel(left + px, top + py, color);
+            }
+        }
+    }
+    Ok(img)
+}
+
+/// Renders only the function patterns of the code (finders, separators, timing, alignment, format
+/// and version information, and the dark module), leaving the data region blank, to show how
+/// codes are structured. Colors, module size and quiet zone come from the options; the border
+/// and shadow are ignored.
+pub fn to_structure_preview(code: &QRCode, options: &RenderOptions) -> RgbImage {
+    let (width, height) = options.effective_module_size();
+    let quiet_zone = options.effective_quiet_zone();
+    let modules = code.version.modules_per_side() as u32 + 2 * quiet_zone;
+    let mut img = RgbImage::from_pixel(width * modules, height * modules, options.background);
+    for ((x, y), module) in code.modules() {
+        if !module.black() || matches!(module, Module::Data(_)) {
+            continue;
+        }
+        let left = (x as u32 + quiet_zone) * width;
+        let top = (y as u32 + quiet_zone) * height;
+        for px in 0..width {
+            for py in 0..height {
+                img.put_pixel(left + px, top + py, options.foreground);
+            }
+        }
+    }
+    img
+}
+
+/// Scales the image up by a whole factor, repeating each pixel in a `factor` by `factor` block,
+/// so a render at one pixel per module can be enlarged on demand without blurring the modules.
+pub fn upscale(img: &RgbImage, factor: u32) -> RgbImage {
+    RgbImage::from_fn(img.width() * factor, img.height() * factor, |x, y| {
+        *img.get_pixel(x / factor, y / factor)
+    })
+}
+
+/// Renders the code with `module_size` pixels per module and draws one-pixel red lines along
+/// every module boundary of the symbol, for checking module alignment on printed codes. The
+/// first pixel row and column of each module are covered by the grid; the rest of the module
+/// keeps its normal colour.
+pub fn to_grid_overlay(code: &QRCode, module_size: u32) -> RgbImage {
+    let mut img = render(code, &RenderOptions::square(module_size));
+    let side = code.version.modules_per_side() as u32;
+    let start = QUIET_ZONE_MODULES * module_size;
+    let end = (QUIET_ZONE_MODULES + side) * module_size;
+    for boundary in 0..=side {
+        let offset = start + boundary * module_size;
+        for along in start..=end {
+            img.put_pixel(offset, along, RED);
+            img.put_pixel(along, offset, RED);
         }
     }
     img
 }
 
+/// Formats a color for CSS, e.g. #1a2b3c.
+fn css_color(color: Rgb<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// Renders the code as an HTML table with one cell per module, for emails and other places that
+/// strip images. Cells are sized and colored by inline CSS from the options, and the quiet zone
+/// is padding around the table in the background color.
+pub fn to_html_table(code: &QRCode, options: &RenderOptions) -> String {
+    let (width, height) = options.effective_module_size();
+    let side = code.version.modules_per_side();
+    let cell = format!("width:{}px;height:{}px;padding:0", width, height);
+    let mut html = format!(
+        "<table cellspacing=\"0\" cellpadding=\"0\" style=\"border-collapse:separate;\
+         border-spacing:0;padding:{}px {}px;background:{}\">\n",
+        options.effective_quiet_zone() * height,
+        options.effective_quiet_zone() * width,
+        css_color(options.background)
+    );
+    for y in 0..side {
+        html.push_str("<tr>");
+        for x in 0..side {
+            if code.rows[x][y].black() {
+                write!(
+                    html,
+                    "<td style=\"{};background:{}\"></td>",
+                    cell,
+                    css_color(options.foreground)
+                )
+                .unwrap();
+            } else {
+                write!(html, "<td style=\"{}\"></td>", cell).unwrap();
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Dot bits of a braille cell, indexed by [x][y] within the 2x4 block.
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+/// Renders the code as lines of Unicode braille characters, each covering a 2x4 block of modules.
+/// Black modules are raised dots; blocks running past the edge of the code are padded with white.
+/// The quiet zone is not included.
+pub fn to_braille(code: &QRCode) -> String {
+    let side = code.version.modules_per_side();
+    let mut out = String::new();
+    for top in (0..side).step_by(4) {
+        for left in (0..side).step_by(2) {
+            let mut cell = 0x2800;
+            for (dx, column) in BRAILLE_DOTS.iter().enumerate() {
+                for (dy, dot) in column.iter().enumerate() {
+                    let (x, y) = (left + dx, top + dy);
+                    if x < side && y < side && code.rows[x][y].black() {
+                        cell |= dot;
+                    }
+                }
+            }
+            out.push(std::char::from_u32(cell).unwrap());
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn text_chunk(key: &str, value: &str) -> Result<Vec<u8>, Error> {
+    if key.is_empty() || key.chars().count() > 79 {
+        return Err(format!("The PNG metadata key {:?} must be 1 to 79 characters", key).into());
+    }
+    let latin1 = |text: &str| {
+        ISO_8859_1
+            .encode(text, EncoderTrap::Strict)
+            .map_err(|_| format!("The PNG metadata {:?} isn't valid ISO-8859-1", text))
+    };
+    let mut chunk = latin1(key)?;
+    chunk.push(0);
+    chunk.append(&mut latin1(value)?);
+    Ok(chunk)
+}
+
+/// Renders the code and encodes it as a PNG in memory, including any metadata in the options.
+pub fn to_png(code: &QRCode, options: &RenderOptions) -> Result<Vec<u8>, Error> {
+    let img = render(code, options);
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, img.width(), img.height());
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    for (key, value) in &options.png_metadata {
+        writer
+            .write_chunk(*b"tEXt", &text_chunk(key, value)?)
+            .map_err(|e| e.to_string())?;
+    }
+    writer.write_image_data(&img).map_err(|e| e.to_string())?;
+    drop(writer);
+    Ok(png)
+}
+
+/// The size of each cell of a grid of the codes: the largest of their rendered images, so codes
+/// of different versions all fit.
+fn grid_cell(codes: &[QRCode], columns: u32, options: &RenderOptions) -> Result<(u32, u32), Error> {
+    if columns == 0 {
+        return Err("A grid needs at least one column".into());
+    }
+    if codes.is_empty() {
+        return Err("A grid needs at least one code".into());
+    }
+    Ok(codes
+        .iter()
+        .map(|code| options.image_dimensions(code.version))
+        .fold((0, 0), |(width, height), (code_width, code_height)| {
+            (width.max(code_width), height.max(code_height))
+        }))
+}
+
+/// Renders one row of a grid, with each code centered in its cell.
+fn grid_band(
+    row: &[QRCode],
+    (cell_width, cell_height): (u32, u32),
+    columns: u32,
+    options: &RenderOptions,
+) -> RgbImage {
+    let mut band = RgbImage::from_pixel(cell_width * columns, cell_height, options.background);
+    for (i, code) in row.iter().enumerate() {
+        let img = render(code, options);
+        let left = i as u32 * cell_width + (cell_width - img.width()) / 2;
+        let top = (cell_height - img.height()) / 2;
+        image::imageops::replace(&mut band, &img, left, top);
+    }
+    band
+}
+
+/// Renders the codes in a grid with this many columns, filling rows left to right, e.g. for a
+/// sheet of labels. Every cell is the size of the largest code's image, with smaller codes
+/// centered in theirs. For large sheets, `render_grid_to_writer` needs far less memory.
+pub fn to_grid(codes: &[QRCode], columns: u32, options: &RenderOptions) -> Result<RgbImage, Error> {
+    let cell = grid_cell(codes, columns, options)?;
+    let rows = codes.len().div_ceil(columns as usize) as u32;
+    let mut grid = RgbImage::new(cell.0 * columns, cell.1 * rows);
+    for (y, row) in codes.chunks(columns as usize).enumerate() {
+        let band = grid_band(row, cell, columns, options);
+        image::imageops::replace(&mut grid, &band, 0, y as u32 * cell.1);
+    }
+    Ok(grid)
+}
+
+/// Writes the same grid as `to_grid` as a PNG, rendering and compressing it a row of codes at a
+/// time, so only one row is ever held in memory rather than the whole sheet.
+pub fn render_grid_to_writer(
+    codes: &[QRCode],
+    columns: u32,
+    options: &RenderOptions,
+    writer: impl io::Write,
+) -> Result<(), Error> {
+    let cell = grid_cell(codes, columns, options)?;
+    let rows = codes.len().div_ceil(columns as usize) as u32;
+    let mut encoder = png::Encoder::new(writer, cell.0 * columns, cell.1 * rows);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut png = encoder.write_header().map_err(|e| e.to_string())?;
+    let mut stream = png.stream_writer();
+    for row in codes.chunks(columns as usize) {
+        let band = grid_band(row, cell, columns, options);
+        io::Write::write_all(&mut stream, &band).map_err(|e| e.to_string())?;
+    }
+    stream.finish().map_err(|e| e.to_string().into())
+}
+
+/// The 3x5 pixel glyph for a caption character, one row per byte with the leftmost pixel in the
+/// highest of the low 3 bits. Only covers what `render_structured_append_sheet` writes.
+fn caption_glyph(character: char) -> [u8; 5] {
+    match character {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'o' => [0b000, 0b000, 0b111, 0b101, 0b111],
+        'f' => [0b011, 0b010, 0b111, 0b010, 0b010],
+        _ => [0; 5],
+    }
+}
+
+/// Draws the caption with each glyph pixel as a `scale` by `scale` square, centered in a strip of
+/// the image `width` pixels wide starting at `left`. Glyphs are 3 pixels wide with a 1 pixel gap.
+fn draw_caption(
+    img: &mut RgbImage,
+    text: &str,
+    (left, top, width): (u32, u32, u32),
+    scale: u32,
+    color: Rgb<u8>,
+) {
+    let text_width = (text.chars().count() as u32 * 4).saturating_sub(1) * scale;
+    let start = left + width.saturating_sub(text_width) / 2;
+    for (i, character) in text.chars().enumerate() {
+        for (row, bits) in caption_glyph(character).iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                let x = start + (i as u32 * 4 + column) * scale;
+                let y = top + row as u32 * scale;
+                for px in x..(x + scale).min(left + width) {
+                    for py in y..y + scale {
+                        img.put_pixel(px, py, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders a structured append set, such as the codes from `create_structured_append`, on one
+/// sheet in order, up to four to a row, each captioned "1 of 3" and so on below it so they can
+/// be scanned in sequence. Captions are drawn in the foreground color, as large as the module
+/// height allows while still fitting under their code.
+pub fn render_structured_append_sheet(
+    codes: &[QRCode],
+    options: &RenderOptions,
+) -> Result<RgbImage, Error> {
+    let columns = codes.len().min(SHEET_COLUMNS) as u32;
+    let cell = grid_cell(codes, columns.max(1), options)?;
+    let captions: Vec<String> = (1..=codes.len())
+        .map(|position| format!("{} of {}", position, codes.len()))
+        .collect();
+    let longest = captions
+        .iter()
+        .map(|caption| caption.len())
+        .max()
+        .unwrap_or(0) as u32;
+    let scale = options
+        .effective_module_size()
+        .1
+        .min(cell.0 / (longest * 4).max(1))
+        .max(1);
+    // the glyphs' 5 pixels, with one of space above and below
+    let caption_height = 7 * scale;
+    let rows = codes.len().div_ceil(columns as usize) as u32;
+    let row_height = cell.1 + caption_height;
+    let mut sheet = RgbImage::from_pixel(cell.0 * columns, row_height * rows, options.background);
+    for (y, row) in codes.chunks(columns as usize).enumerate() {
+        let top = y as u32 * row_height;
+        let band = grid_band(row, cell, columns, options);
+        image::imageops::replace(&mut sheet, &band, 0, top);
+        for x in 0..row.len() {
+            let caption = &captions[y * columns as usize + x];
+            let strip = (x as u32 * cell.0, top + cell.1 + scale, cell.0);
+            draw_caption(&mut sheet, caption, strip, scale, options.foreground);
+        }
+    }
+    Ok(sheet)
+}
+
 fn save_image(img: &RgbImage, path: &Path) -> Result<(), Error> {
     img.save(path).map_err(|e| e.to_string().into())
 }
 
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
 pub fn save_qrcode(code: &QRCode, path: &Path) -> Result<(), Error> {
-    let buffer = modules_to_buffer(code);
-    save_image(&buffer, path)
+    save_qrcode_with(code, path, &RenderOptions::default())
+}
+
+/// Saves the code in the format given by the file extension. PNG files get the options' metadata.
+pub fn save_qrcode_with(code: &QRCode, path: &Path, options: &RenderOptions) -> Result<(), Error> {
+    if is_png(path) {
+        std::fs::write(path, to_png(code, options)?).map_err(|e| e.to_string().into())
+    } else {
+        save_image(&render(code, options), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_qr_code;
+    use crate::qr::error_correction::ErrorCorrectionLevel;
+
+    #[test]
+    fn test_non_square_modules() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Quartile).unwrap();
+        let options = RenderOptions {
+            module_width: 4,
+            module_height: 6,
+            ..RenderOptions::default()
+        };
+        let img = render(&code, &options);
+        // 21 modules plus a quiet zone of 4 on each side
+        assert_eq!(img.dimensions(), (29 * 4, 29 * 6));
+        // the top-left finder corner module covers a 4x6 block
+        let (left, top) = (QUIET_ZONE_MODULES * 4, QUIET_ZONE_MODULES * 6);
+        assert_eq!(*img.get_pixel(left + 3, top + 5), BLACK);
+        assert_eq!(*img.get_pixel(left + 4, top + 6), WHITE);
+    }
+
+    #[test]
+    fn test_odd_module_size() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Quartile).unwrap();
+        let options = RenderOptions {
+            ensure_odd_module_size: true,
+            ..RenderOptions::square(4)
+        };
+        assert_eq!(options.effective_module_size(), (5, 5));
+        assert_eq!(render(&code, &options).dimensions(), (29 * 5, 29 * 5));
+        assert_eq!(RenderOptions::square(4).effective_module_size(), (4, 4));
+
+        let options = RenderOptions {
+            module_width: 3,
+            module_height: 6,
+            ensure_odd_module_size: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(options.effective_module_size(), (3, 7));
+    }
+
+    #[test]
+    fn test_ocean_theme() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let theme = Theme::ocean();
+        let img = render_themed(&code, &theme, &RenderOptions::square(5));
+        let pixel = |(x, y): (usize, usize), (dx, dy): (u32, u32)| {
+            let origin = QUIET_ZONE_MODULES * 5;
+            *img.get_pixel(origin + x as u32 * 5 + dx, origin + y as u32 * 5 + dy)
+        };
+        // the top left finder's outer ring and center, then its light ring
+        assert_eq!(pixel((0, 0), (2, 2)), theme.finder);
+        assert_eq!(pixel((3, 3), (0, 0)), theme.finder);
+        assert_eq!(pixel((1, 1), (2, 2)), theme.background);
+        // the first dark timing module after the finder
+        assert_eq!(pixel((8, 6), (2, 2)), theme.timing);
+        assert_eq!(*img.get_pixel(0, 0), theme.background);
+        for ((x, y), module) in code.modules() {
+            if let Module::Data(true) = module {
+                assert_eq!(pixel((x, y), (2, 2)), theme.foreground);
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_modules() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let theme = Theme::sunset();
+        let img = render_themed(&code, &theme, &RenderOptions::square(5));
+        let origin = QUIET_ZONE_MODULES * 5;
+        // the corners of a round finder module stay background, its center doesn't
+        assert_eq!(*img.get_pixel(origin, origin), theme.background);
+        assert_eq!(*img.get_pixel(origin + 2, origin + 2), theme.finder);
+        assert_eq!(*img.get_pixel(origin + 2, origin), theme.finder);
+    }
+
+    #[test]
+    fn test_scannability() {
+        let version = Version::by_num(1);
+        assert_eq!(RenderOptions::default().check_scannability(version), vec![]);
+
+        let options = RenderOptions {
+            quiet_zone: 1,
+            ..RenderOptions::square(2)
+        };
+        assert_eq!(
+            options.check_scannability(version),
+            vec![
+                Warning::QuietZoneTooSmall { modules: 1 },
+                Warning::ModuleTooSmall { pixels: 2 },
+                Warning::SymbolTooSmall { pixels: 42 },
+            ]
+        );
+        // the same module size is fine at a larger version
+        assert_eq!(
+            RenderOptions::square(3).check_scannability(Version::by_num(10)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_scannability_colors() {
+        let version = Version::by_num(1);
+        let options = RenderOptions {
+            foreground: Rgb([120, 120, 120]),
+            background: Rgb([160, 160, 160]),
+            ..RenderOptions::default()
+        };
+        let warnings = options.check_scannability(version);
+        assert!(matches!(warnings[..], [Warning::LowContrast { ratio }] if ratio < 2.0));
+
+        let options = RenderOptions {
+            foreground: WHITE,
+            background: BLACK,
+            ..RenderOptions::default()
+        };
+        assert_eq!(options.check_scannability(version), vec![Warning::Inverted]);
+    }
+
+    #[test]
+    fn test_bleed() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let options = RenderOptions {
+            module_width: 1,
+            module_height: 1,
+            bleed_modules: 3,
+            ..RenderOptions::default()
+        };
+        assert_eq!(options.effective_quiet_zone(), 7);
+        let img = render(&code, &options);
+        assert_eq!(img.dimensions(), (21 + 2 * 7, 21 + 2 * 7));
+        assert_eq!(options.image_dimensions(code.version), img.dimensions());
+        // the first dark pixel is the top left finder's corner, 7 modules in
+        let first_dark = img
+            .enumerate_pixels()
+            .find(|(_, _, pixel)| **pixel == BLACK);
+        assert_eq!(first_dark.map(|(x, y, _)| (x, y)), Some((7, 7)));
+        // bleed is trimmed off, so it doesn't count towards the quiet zone the spec asks for
+        let trimmed = RenderOptions {
+            quiet_zone: 2,
+            bleed_modules: 3,
+            ..RenderOptions::default()
+        };
+        assert!(trimmed
+            .check_scannability(code.version)
+            .contains(&Warning::QuietZoneTooSmall { modules: 2 }));
+    }
+
+    #[test]
+    fn test_quiet_zone() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let options = RenderOptions {
+            quiet_zone: 1,
+            ..RenderOptions::square(2)
+        };
+        let img = render(&code, &options);
+        assert_eq!(img.dimensions(), (23 * 2, 23 * 2));
+        assert_eq!(*img.get_pixel(1, 1), WHITE);
+        assert_eq!(*img.get_pixel(2, 2), BLACK);
+    }
+
+    /// Returns the contents of every chunk of this type, checking the PNG structure on the way.
+    fn read_chunks(png: &[u8], chunk_type: &[u8; 4]) -> Vec<Vec<u8>> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            if &rest[4..8] == chunk_type {
+                chunks.push(rest[8..8 + length].to_vec());
+            }
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn test_png_metadata() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let options = RenderOptions {
+            png_metadata: vec![
+                ("Comment".to_string(), "HELLO WORLD".to_string()),
+                ("Version".to_string(), "1-M".to_string()),
+            ],
+            ..RenderOptions::default()
+        };
+        let png = to_png(&code, &options).unwrap();
+        assert_eq!(
+            read_chunks(&png, b"tEXt"),
+            vec![b"Comment\0HELLO WORLD".to_vec(), b"Version\x001-M".to_vec()]
+        );
+        let img = image::load_from_memory(&png).unwrap().to_rgb();
+        assert_eq!(img, render(&code, &options));
+
+        let path =
+            std::env::temp_dir().join(format!("qrust-png-metadata-{}.png", std::process::id()));
+        save_qrcode_with(&code, &path, &options).unwrap();
+        let saved = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_chunks(&saved, b"tEXt").len(), 2);
+    }
+
+    #[test]
+    fn test_png_metadata_invalid() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        for (key, value) in &[("", "empty"), ("Title", "Привет")] {
+            let options = RenderOptions {
+                png_metadata: vec![(key.to_string(), value.to_string())],
+                ..RenderOptions::default()
+            };
+            assert!(to_png(&code, &options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_inverted_frame() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::High).unwrap();
+        let options = RenderOptions::default();
+        let frame = ModuleRect {
+            x: 8,
+            y: 8,
+            width: 5,
+            height: 4,
+        };
+        let plain = render(&code, &options);
+        let framed = with_inverted_frame(&code, frame, &options).unwrap();
+        for ((x, y), _) in code.modules() {
+            let (px, py) = ((x as u32 + 4) * 4 + 1, (y as u32 + 4) * 4 + 1);
+            let inside = (8..13).contains(&x) && (8..12).contains(&y);
+            let expected = match (*plain.get_pixel(px, py), inside) {
+                (color, false) => color,
+                (BLACK, true) => WHITE,
+                (_, true) => BLACK,
+            };
+            assert_eq!(*framed.get_pixel(px, py), expected, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn test_inverted_frame_errors() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::High).unwrap();
+        let options = RenderOptions::default();
+        // covers a corner of the top left finder
+        let frame = ModuleRect {
+            x: 5,
+            y: 5,
+            width: 3,
+            height: 3,
+        };
+        assert!(with_inverted_frame(&code, frame, &options).is_err());
+        // runs off the right edge of the 25 module symbol
+        let frame = ModuleRect {
+            x: 22,
+            y: 18,
+            width: 5,
+            height: 1,
+        };
+        assert!(with_inverted_frame(&code, frame, &options).is_err());
+    }
+
+    #[test]
+    fn test_border() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let options = RenderOptions {
+            border: Some(Border {
+                width: 2,
+                color: RED,
+            }),
+            ..RenderOptions::default()
+        };
+        let img = render(&code, &options);
+        // 29 modules of 4 pixels, plus the border on both sides
+        let side = 29 * 4 + 4;
+        assert_eq!(img.dimensions(), (side, side));
+        for i in 0..side {
+            for edge in &[0, 1, side - 2, side - 1] {
+                assert_eq!(*img.get_pixel(i, *edge), RED);
+                assert_eq!(*img.get_pixel(*edge, i), RED);
+            }
+        }
+        // the quiet zone starts just inside the border, and the symbol 4 modules further in
+        assert_eq!(*img.get_pixel(2, 2), WHITE);
+        assert_eq!(*img.get_pixel(side - 3, side - 3), WHITE);
+        assert_eq!(*img.get_pixel(2 + 16, 2 + 16), BLACK);
+    }
+
+    #[test]
+    fn test_shadow() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let shadow = Shadow::default();
+        let options = RenderOptions {
+            shadow: Some(shadow.clone()),
+            ..RenderOptions::default()
+        };
+        let img = render(&code, &options);
+        let side = 29 * 4;
+        assert_eq!(img.dimensions(), (side + 4, side + 4));
+        assert_eq!(*img.get_pixel(side + 1, side + 1), shadow.color);
+        assert_eq!(*img.get_pixel(side + 1, 1), WHITE);
+        assert_eq!(*img.get_pixel(side - 1, side - 1), WHITE);
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let decorated = RenderOptions {
+            module_width: 4,
+            module_height: 6,
+            ensure_odd_module_size: true,
+            border: Some(Border {
+                width: 3,
+                color: RED,
+            }),
+            shadow: Some(Shadow::default()),
+            ..RenderOptions::default()
+        };
+        for options in &[RenderOptions::default(), decorated] {
+            assert_eq!(
+                options.image_dimensions(code.version),
+                render(&code, options).dimensions()
+            );
+        }
+    }
+
+    #[test]
+    fn test_on_canvas() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let options = RenderOptions::default();
+        let img = on_canvas(&code, (1080, 1080), RED, &options).unwrap();
+        assert_eq!(img.dimensions(), (1080, 1080));
+        // the 116 pixel code starts 482 pixels in, with its finder 16 pixels further
+        let offset = (1080 - 29 * 4) / 2;
+        assert_eq!(offset, 482);
+        assert_eq!(*img.get_pixel(offset - 1, 540), RED);
+        assert_eq!(*img.get_pixel(offset, 540), WHITE);
+        assert_eq!(*img.get_pixel(offset + 16, offset + 16), BLACK);
+        assert_eq!(*img.get_pixel(offset + 116, 540), RED);
+        assert_eq!(*img.get_pixel(0, 0), RED);
+        assert_eq!(*img.get_pixel(1079, 1079), RED);
+
+        assert!(on_canvas(&code, (100, 1080), RED, &options).is_err());
+    }
+
+    #[test]
+    fn test_thermal_bitmap() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        // 29 modules at 13 dots each across a 384 dot printer, with 3 dots left over each side
+        let width = 384;
+        let bitmap = to_thermal_bitmap(&code, width).unwrap();
+        let height = 29 * 13;
+        assert_eq!(bitmap.len(), (width as usize).div_ceil(8) * height);
+        let dot = |x: usize, y: usize| bitmap[y * 48 + x / 8] & (0x80 >> (x % 8)) != 0;
+        let (left, top) = (3 + 4 * 13, 4 * 13);
+        assert!(!dot(left - 1, top));
+        assert!(dot(left, top));
+        // the finder's white ring starts at its second module
+        assert!(dot(left + 12, top + 12));
+        assert!(!dot(left + 13, top + 13));
+
+        // odd widths still pad each row to a whole byte
+        let bitmap = to_thermal_bitmap(&code, 30).unwrap();
+        assert_eq!(bitmap.len(), 4 * 29);
+        assert!(to_thermal_bitmap(&code, 28).is_err());
+    }
+
+    #[test]
+    fn test_render_layers() {
+        let code =
+            create_qr_code("https://example.com/layers", ErrorCorrectionLevel::Medium).unwrap();
+        assert!(code.version.num >= 2);
+        let options = RenderOptions::default();
+        let layers = render_layers(&code, &options);
+        let full = render(&code, &options);
+        assert_eq!(layers.finders.dimensions(), full.dimensions());
+        let mut alignment_pixels = 0;
+        for (x, y, pixel) in full.enumerate_pixels() {
+            let masks = [&layers.finders, &layers.alignment, &layers.data]
+                .iter()
+                .filter(|layer| layer.get_pixel(x, y)[0] == 255)
+                .count();
+            assert!(masks <= 1, "layers overlap at ({}, {})", x, y);
+            assert_eq!(masks == 1, *pixel == BLACK, "mismatch at ({}, {})", x, y);
+            if layers.alignment.get_pixel(x, y)[0] == 255 {
+                alignment_pixels += 1;
+            }
+        }
+        // one alignment pattern has 17 black modules
+        assert_eq!(alignment_pixels, 17 * 16);
+    }
+
+    #[test]
+    fn test_render_rgba() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let options = RenderOptions {
+            foreground_alpha: 200,
+            background_alpha: 0,
+            ..RenderOptions::default()
+        };
+        let img = render_rgba(&code, &options);
+        // the top left finder's outer ring, past the 4 module quiet zone
+        assert_eq!(*img.get_pixel(16, 16), Rgba([0, 0, 0, 200]));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 255, 255, 0]));
+        assert!(options
+            .check_scannability(code.version)
+            .contains(&Warning::TranslucentForeground { alpha: 200 }));
+        assert!(RenderOptions::default()
+            .check_scannability(code.version)
+            .is_empty());
+
+        // a border and shadow in the module color stay opaque
+        let options = RenderOptions {
+            foreground_alpha: 128,
+            border: Some(Border {
+                width: 2,
+                color: BLACK,
+            }),
+            shadow: Some(Shadow {
+                offset: 3,
+                color: BLACK,
+            }),
+            ..options
+        };
+        let img = render_rgba(&code, &options);
+        let (width, height) = img.dimensions();
+        assert_eq!(*img.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(width - 1, height - 1), Rgba([0, 0, 0, 255]));
+        // the shadow's offset leaves background in the corners
+        assert_eq!(*img.get_pixel(width - 1, 0), Rgba([255, 255, 255, 0]));
+        // the modules and quiet zone are still translucent, inside the border
+        assert_eq!(*img.get_pixel(2 + 16, 2 + 16), Rgba([0, 0, 0, 128]));
+        assert_eq!(*img.get_pixel(2, 2), Rgba([255, 255, 255, 0]));
+        // the colors are the same as the opaque rendering's
+        let rgb = RgbImage::from_fn(width, height, |x, y| img.get_pixel(x, y).to_rgb());
+        assert_eq!(rgb, render(&code, &options));
+    }
+
+    #[test]
+    fn test_module_size_for_physical() {
+        // 0.5mm is 5.9 pixels at 300 DPI
+        assert_eq!(RenderOptions::module_size_for_physical(0.5, 300), 6);
+        assert_eq!(RenderOptions::module_size_for_physical(0.5, 600), 12);
+        assert_eq!(RenderOptions::module_size_for_physical(0.0, 300), 1);
+    }
+
+    #[test]
+    fn test_structure_preview() {
+        let code =
+            create_qr_code("https://example.com/preview", ErrorCorrectionLevel::Medium).unwrap();
+        let options = RenderOptions::default();
+        let preview = to_structure_preview(&code, &options);
+        let full = render(&code, &options);
+        assert_eq!(preview.dimensions(), full.dimensions());
+        let (width, height) = options.effective_module_size();
+        let quiet_zone = options.quiet_zone;
+        for ((x, y), module) in code.modules() {
+            let pixel = preview.get_pixel(
+                (x as u32 + quiet_zone) * width,
+                (y as u32 + quiet_zone) * height,
+            );
+            match module {
+                Module::Data(_) => assert_eq!(*pixel, WHITE, "data drawn at ({}, {})", x, y),
+                _ => assert_eq!(
+                    *pixel == BLACK,
+                    module.black(),
+                    "mismatch at ({}, {})",
+                    x,
+                    y
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let options = RenderOptions::default();
+        let same = diff(&code, &code, &options).unwrap();
+        assert!(same.pixels().all(|pixel| *pixel != RED));
+
+        let mut changed = code.clone();
+        changed.rows[10][12] = Module::Data(!code.rows[10][12].black());
+        let different = diff(&code, &changed, &options).unwrap();
+        let red: Vec<(u32, u32)> = different
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == RED)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(red.len(), 16);
+        assert_eq!(red[0], ((10 + 4) * 4, (12 + 4) * 4));
+
+        let larger =
+            create_qr_code(&"HELLO WORLD".repeat(4), ErrorCorrectionLevel::Medium).unwrap();
+        assert!(diff(&code, &larger, &options).is_err());
+    }
+
+    #[test]
+    fn test_validate_colors() {
+        assert!(RenderOptions::default().validate_colors().is_ok());
+        let light_gray = RenderOptions {
+            foreground: Rgb([200, 200, 200]),
+            ..RenderOptions::default()
+        };
+        assert!(light_gray.validate_colors().is_err());
+        // enough to avoid the scannability warning, but not to pass validation
+        let mid_gray = RenderOptions {
+            foreground: Rgb([110, 110, 110]),
+            ..RenderOptions::default()
+        };
+        assert!(mid_gray.check_scannability(Version::by_num(1)).is_empty());
+        assert!(mid_gray.validate_colors().is_err());
+    }
+
+    #[test]
+    fn test_upscale() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let small = render(
+            &code,
+            &RenderOptions {
+                quiet_zone: 0,
+                ..RenderOptions::square(1)
+            },
+        );
+        assert_eq!(small.dimensions(), (21, 21));
+        let large = upscale(&small, 10);
+        assert_eq!(large.dimensions(), (210, 210));
+        for (x, y, pixel) in large.enumerate_pixels() {
+            assert_eq!(pixel, small.get_pixel(x / 10, y / 10));
+        }
+        assert_eq!(
+            large,
+            render(
+                &code,
+                &RenderOptions {
+                    quiet_zone: 0,
+                    ..RenderOptions::square(10)
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_html_table() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let html = to_html_table(&code, &RenderOptions::default());
+        assert!(html.starts_with("<table "));
+        assert!(html.contains("padding:16px 16px;background:#ffffff"));
+        assert_eq!(html.matches("<tr>").count(), 21);
+        assert_eq!(html.matches("<td ").count(), 21 * 21);
+        let black_modules = code.modules().filter(|(_, module)| module.black()).count();
+        assert_eq!(
+            html.matches(";background:#000000\"></td>").count(),
+            black_modules
+        );
+    }
+
+    #[test]
+    fn test_module_gutter() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let options = RenderOptions {
+            module_gutter: 2,
+            ..RenderOptions::square(6)
+        };
+        let img = render(&code, &options);
+        let ((x, y), _) = code
+            .modules()
+            .find(|(_, module)| matches!(module, Module::Data(true)))
+            .unwrap();
+        let (left, top) = ((x as u32 + 4) * 6, (y as u32 + 4) * 6);
+        assert_eq!(*img.get_pixel(left, top + 3), WHITE);
+        assert_eq!(*img.get_pixel(left + 5, top + 3), WHITE);
+        assert_eq!(*img.get_pixel(left + 3, top + 3), BLACK);
+        assert_eq!(*img.get_pixel(left + 1, top + 1), BLACK);
+        // the finder's outer ring stays solid across its modules
+        assert_eq!(*img.get_pixel(4 * 6, 4 * 6), BLACK);
+        assert_eq!(*img.get_pixel(5 * 6 - 1, 4 * 6), BLACK);
+    }
+
+    #[test]
+    fn test_grid() {
+        let codes: Vec<QRCode> = (0..5)
+            .map(|i| {
+                crate::QRCodeBuilder::new(&format!("Sheet code {}", i))
+                    .version(10)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        let options = RenderOptions::default();
+        let (width, height) = options.image_dimensions(codes[0].version);
+        let grid = to_grid(&codes, 3, &options).unwrap();
+        assert_eq!(grid.dimensions(), (3 * width, 2 * height));
+        // the last cell of the second row is left blank
+        assert!(grid
+            .enumerate_pixels()
+            .filter(|(x, y, _)| *x >= 2 * width && *y >= height)
+            .all(|(_, _, pixel)| *pixel == WHITE));
+
+        let mut png = Vec::new();
+        render_grid_to_writer(&codes, 3, &options, &mut png).unwrap();
+        let streamed = image::load_from_memory(&png).unwrap().to_rgb();
+        assert_eq!(streamed, grid);
+
+        assert!(to_grid(&codes, 0, &options).is_err());
+        assert!(render_grid_to_writer(&[], 3, &options, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_structured_append_sheet() {
+        let data = "the quick brown fox jumps over a lazy dog";
+        let codes = crate::create_structured_append(data, 1, ErrorCorrectionLevel::Low).unwrap();
+        assert_eq!(codes.len(), 3);
+        let options = RenderOptions::default();
+        let sheet = render_structured_append_sheet(&codes, &options).unwrap();
+        let (width, height) = options.image_dimensions(codes[0].version);
+        assert_eq!(sheet.width(), 3 * width);
+        assert!(sheet.height() > height);
+        for cell in 0..3 {
+            let caption_pixels = (cell * width..(cell + 1) * width)
+                .flat_map(|x| (height..sheet.height()).map(move |y| (x, y)))
+                .filter(|&(x, y)| *sheet.get_pixel(x, y) != WHITE)
+                .count();
+            assert!(caption_pixels > 0, "no caption under code {}", cell);
+            // the code itself is drawn above its caption
+            let code = render(&codes[cell as usize], &options);
+            assert_eq!(
+                image::imageops::crop_imm(&sheet, cell * width, 0, width, height).to_image(),
+                code
+            );
+        }
+        assert!(render_structured_append_sheet(&[], &options).is_err());
+    }
+
+    #[test]
+    fn test_caption_glyphs() {
+        let mut img = RgbImage::from_pixel(15, 5, WHITE);
+        draw_caption(&mut img, "1 of", (0, 0, 15), 1, BLACK);
+        let rows: Vec<String> = (0..5)
+            .map(|y| {
+                (0..15)
+                    .map(|x| {
+                        if *img.get_pixel(x, y) == BLACK {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ".#...........##",
+                "##...........#.",
+                ".#......###.###",
+                ".#......#.#..#.",
+                "###.....###..#.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grid_overlay() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Quartile).unwrap();
+        let module_size = 6;
+        let img = to_grid_overlay(&code, module_size);
+        let side = code.version.modules_per_side() as u32;
+        let origin = QUIET_ZONE_MODULES * module_size;
+        for (x, column) in code.rows.iter().enumerate() {
+            for (y, module) in column.iter().enumerate() {
+                let left = origin + x as u32 * module_size;
+                let top = origin + y as u32 * module_size;
+                assert_eq!(*img.get_pixel(left, top), RED);
+                assert_eq!(*img.get_pixel(left + 2, top), RED);
+                assert_eq!(*img.get_pixel(left, top + 2), RED);
+                let expected = if module.black() { BLACK } else { WHITE };
+                for px in (left + 1)..(left + module_size) {
+                    for py in (top + 1)..(top + module_size) {
+                        assert_eq!(*img.get_pixel(px, py), expected);
+                    }
+                }
+            }
+        }
+        let far_edge = origin + side * module_size;
+        assert_eq!(*img.get_pixel(far_edge, origin + 3), RED);
+        assert_eq!(*img.get_pixel(origin + 3, far_edge), RED);
+        assert_eq!(*img.get_pixel(0, 0), WHITE);
+    }
+
+    #[test]
+    fn test_braille() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Quartile).unwrap();
+        assert_eq!(code.version.num, 1);
+        let braille = to_braille(&code);
+        let lines: Vec<&str> = braille.lines().collect();
+        assert_eq!(lines.len(), 6);
+        for line in &lines {
+            assert_eq!(line.chars().count(), 11);
+        }
+        // top-left corner of the finder: a solid left column, with only the top of the right one
+        assert_eq!(lines[0].chars().next(), Some('\u{284F}'));
+        // the last line only covers the bottom row, whose left end is the finder's bottom edge
+        assert_eq!(lines[5].chars().next(), Some('\u{2809}'));
+    }
 }
@@ -0,0 +1,76 @@
+use crate::qr::pattern::QRCode;
+use std::fmt::Write;
+
+/// Formats a number with a comma between each group of three digits, e.g. 2956 as "2,956".
+pub fn group_digits(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Describes the version and codeword usage of a code for people to read, one fact per line.
+pub fn describe(code: &QRCode) -> String {
+    let side = code.version.modules_per_side();
+    let mut out = String::new();
+    writeln!(
+        out,
+        "Version: {} ({}x{} modules)",
+        code.version.num, side, side
+    )
+    .unwrap();
+    if let Some(stats) = &code.stats {
+        let data_codewords = stats.codewords_before_padding + stats.padding_codewords;
+        writeln!(out, "Error correction level: {:?}", stats.ecl).unwrap();
+        writeln!(out, "Mode: {:?}", stats.encoding).unwrap();
+        if let Some(mask) = code.mask {
+            writeln!(out, "Mask: {}", mask).unwrap();
+        }
+        writeln!(
+            out,
+            "Data: {} codewords ({} used, {} padding)",
+            group_digits(data_codewords),
+            group_digits(stats.codewords_before_padding),
+            group_digits(stats.padding_codewords)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "Error correction: {} codewords",
+            group_digits(stats.ec_codewords)
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::error_correction::ErrorCorrectionLevel;
+    use crate::QRCodeBuilder;
+
+    #[test]
+    fn test_group_digits() {
+        assert_eq!(group_digits(2956), "2,956");
+        assert_eq!(group_digits(0), "0");
+        assert_eq!(group_digits(956), "956");
+        assert_eq!(group_digits(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_describe() {
+        let code = QRCodeBuilder::new(&"1".repeat(7000))
+            .ecl(ErrorCorrectionLevel::Low)
+            .build()
+            .unwrap();
+        let info = describe(&code);
+        assert!(info.starts_with("Version: 40 (177x177 modules)\n"));
+        assert!(info.contains("Data: 2,956 codewords"));
+    }
+}
@@ -2,14 +2,22 @@ use std::borrow::Cow;
 
 use bitvec::prelude::*;
 
+pub mod decode;
+pub mod draw;
 pub mod encode;
 pub mod error_correction;
 pub mod image;
+pub mod info;
 pub mod pattern;
+pub mod payload;
+pub mod svg;
 pub mod version;
 
 pub type Error = Cow<'static, str>;
 
+/// A grid of modules as booleans (true for black), indexed by x and then y.
+pub type BitMatrix = Vec<Vec<bool>>;
+
 pub(crate) type QREncodedData = BitVec<Lsb0, u8>;
 
 pub(crate) fn insert_into_data(data: &mut QREncodedData, mut value: u16, count_bits: usize) {
@@ -1,9 +1,27 @@
-use crate::qr::image::save_qrcode;
-use crate::qr::version::Version;
-use crate::qr::{Error, QREncodedData};
+use crate::qr::draw::{draw_commands, DrawCommand};
+use crate::qr::encode::QREncoding;
+use crate::qr::error_correction::ErrorCorrectionLevel;
+use crate::qr::image::{render, save_qrcode, RenderOptions};
+use crate::qr::version::{Version, VersionEclData};
+use crate::qr::{BitMatrix, Error, QREncodedData};
+use image::RgbImage;
 use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 const FIRST_POSITION: i32 = 6;
+/// Rule of thumb for how far away a code scans well, as a multiple of its width.
+const SCAN_DISTANCE_RATIO: f32 = 10.0;
+/// Damage rarely lines up with codeword boundaries, so only count on half the modules of the
+/// correctable codewords.
+const OBSCURABLE_SAFETY_FACTOR: f32 = 0.5;
+
+/// Roughly how many modules of a code with this version and ECL can be covered with the code
+/// still decoding, as for `QRCode::max_obscurable_modules`.
+pub(crate) fn obscurable_modules(ecl_data: &VersionEclData) -> usize {
+    let correctable_codewords = ecl_data.total_ec_codewords() / 2;
+    (correctable_codewords as f32 * 8.0 * OBSCURABLE_SAFETY_FACTOR) as usize
+}
 
 /// Calculates the alignment pattern centers, according to Table E.1 of the spec.
 /// Algorithm from StackOverflow:
@@ -28,7 +46,137 @@ fn alignment_pattern_centers(version_num: u8) -> Vec<usize> {
     positions
 }
 
-type Coordinates = (usize, usize);
+pub(crate) type Coordinates = (usize, usize);
+
+// Penalty weights for the mask evaluation rules, from section 7.8.3 of the spec.
+const PENALTY_RUN: u32 = 3;
+const PENALTY_BLOCK: u32 = 3;
+const PENALTY_FINDER_LIKE: u32 = 40;
+const PENALTY_BALANCE: u32 = 10;
+/// A dark-light-dark run in 1:1:3:1:1 proportion, followed by four light modules.
+const FINDER_LIKE: [bool; 11] = [
+    true, false, true, true, true, false, true, false, false, false, false,
+];
+
+#[cfg(test)]
+thread_local! {
+    /// How many modules the penalty rules have read on this thread, so tests can compare how much
+    /// work each mask selection does.
+    static MODULES_SCORED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Counts the modules a penalty rule reads. Does nothing outside of tests.
+fn count_scored(_modules: usize) {
+    #[cfg(test)]
+    MODULES_SCORED.with(|count| count.set(count.get() + _modules));
+}
+
+/// Scores one row or column against rule 1: runs of five or more modules of the same color.
+fn run_penalty(line: &[bool]) -> u32 {
+    count_scored(line.len());
+    let mut penalty = 0;
+    let mut run = 1;
+    for i in 1..=line.len() {
+        if i < line.len() && line[i] == line[i - 1] {
+            run += 1;
+            continue;
+        }
+        if run >= 5 {
+            penalty += PENALTY_RUN + (run - 5);
+        }
+        run = 1;
+    }
+    penalty
+}
+
+/// Scores one row or column against rules 1 and 3 (patterns that look like part of a finder).
+fn line_penalty(line: &[bool]) -> u32 {
+    count_scored(line.len());
+    let finder_like = line
+        .windows(FINDER_LIKE.len())
+        .filter(|window| *window == FINDER_LIKE || window.iter().eq(FINDER_LIKE.iter().rev()))
+        .count() as u32;
+    run_penalty(line) + finder_like * PENALTY_FINDER_LIKE
+}
+
+/// Scores the balance of dark and light modules, rule 4: 10 for each 5% step the dark modules
+/// stray from half of the symbol beyond the first, rounding partial steps up. So 55% dark scores
+/// nothing, and anything past it up to 60% scores 10.
+fn balance_penalty(matrix: &BitMatrix) -> u32 {
+    let total = matrix.len() * matrix.len();
+    count_scored(total);
+    let dark = matrix.iter().flatten().filter(|&&black| black).count();
+    let deviation = (dark * 20).abs_diff(total * 10);
+    PENALTY_BALANCE * deviation.div_ceil(total).saturating_sub(1) as u32
+}
+
+/// Scores a symbol with all four rules.
+fn full_penalty(matrix: &BitMatrix) -> u32 {
+    let side = matrix.len();
+    let mut penalty = 0;
+    for x in 0..side {
+        penalty += line_penalty(&matrix[x]);
+        let row: Vec<bool> = matrix.iter().map(|column| column[x]).collect();
+        penalty += line_penalty(&row);
+    }
+    count_scored(side * side);
+    for x in 1..side {
+        for y in 1..side {
+            let color = matrix[x][y];
+            if matrix[x - 1][y] == color
+                && matrix[x][y - 1] == color
+                && matrix[x - 1][y - 1] == color
+            {
+                penalty += PENALTY_BLOCK;
+            }
+        }
+    }
+    penalty + balance_penalty(matrix)
+}
+
+/// Estimates the score from rules 1 and 4 only, and only counts runs down the columns.
+fn fast_penalty(matrix: &BitMatrix) -> u32 {
+    matrix.iter().map(|column| run_penalty(column)).sum::<u32>() + balance_penalty(matrix)
+}
+
+/// How the mask for a generated code is picked.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MaskSelection {
+    /// Scores each mask with all four of the spec's penalty rules and picks the lowest.
+    #[default]
+    Full,
+    /// Scores each mask only by its runs down the columns and its balance of dark and light
+    /// modules, which is much less work. Any mask makes a valid code, so this only risks a
+    /// slightly harder to scan one.
+    Fast,
+    /// Among the masks scoring within a tenth of the best full penalty, picks the one with the
+    /// most changes between dark and light modules, so the symbol looks as noisy as possible and
+    /// gives away less of the payload's structure at a glance. The spec doesn't ask for this, but
+    /// any mask makes a valid code.
+    MaxEntropy,
+    /// Always uses the given mask, from 0 to 7, e.g. to reproduce a reference code exactly.
+    Fixed(u8),
+}
+
+/// How far above the best penalty, as a fraction of it, `MaskSelection::MaxEntropy` looks.
+const MAX_ENTROPY_PENALTY_SLACK: u32 = 10;
+
+/// Counts the places where neighbouring modules, across or down, differ in color.
+fn transition_count(matrix: &BitMatrix) -> usize {
+    let side = matrix.len();
+    let mut transitions = 0;
+    for x in 0..side {
+        for y in 0..side {
+            if x + 1 < side && matrix[x][y] != matrix[x + 1][y] {
+                transitions += 1;
+            }
+            if y + 1 < side && matrix[x][y] != matrix[x][y + 1] {
+                transitions += 1;
+            }
+        }
+    }
+    transitions
+}
 
 /// Returns all the coordinates of the centers of the alignment patterns for the version number.
 /// Does not exclude the patterns that overlap with finder patterns; the caller must handle that.
@@ -43,8 +191,85 @@ fn alignment_pattern_coordinates(version_num: u8) -> Vec<Coordinates> {
     coords
 }
 
+/// Returns the coordinates of both copies of the format information, indexed by bit position
+/// (bit 0 is the least significant). The first copy wraps around the top left finder, skipping
+/// the timing patterns; the second is split between the top right (bits 0-7) and bottom left
+/// (bits 8-14) finders. Spec: 7.9.1, Figure 25
+pub(crate) fn format_information_coordinates(side: usize) -> [[Coordinates; 15]; 2] {
+    let mut first_copy = [(0, 0); 15];
+    let mut second_copy = [(0, 0); 15];
+    for (i, coords) in first_copy.iter_mut().enumerate() {
+        *coords = match i {
+            0..=5 => (8, i),
+            6 => (8, 7),
+            7 => (8, 8),
+            8 => (7, 8),
+            _ => (14 - i, 8),
+        };
+    }
+    for (i, coords) in second_copy.iter_mut().enumerate() {
+        *coords = match i {
+            0..=7 => (side - 1 - i, 8),
+            _ => (8, side - 15 + i),
+        };
+    }
+    [first_copy, second_copy]
+}
+
+/// Computes the 15 bit format string for an ECL and mask, as described in section 8.9 of the spec:
+/// the 2 ECL bits and 3 mask bits, followed by 10 BCH(15,5) error correction bits, XORed with
+/// `0x5412` so the string is never all zeroes.
+pub(crate) fn format_information(ecl: &ErrorCorrectionLevel, mask: u8) -> u16 {
+    // Spec: 8.9, Table 12
+    let ecl_bits = match ecl {
+        ErrorCorrectionLevel::Low => 0b01,
+        ErrorCorrectionLevel::Medium => 0b00,
+        ErrorCorrectionLevel::Quartile => 0b11,
+        ErrorCorrectionLevel::High => 0b10,
+    };
+    let data = (ecl_bits << 3 | mask as u16) << 10;
+    let mut remainder = data;
+    for i in (10..15).rev() {
+        if remainder & (1 << i) != 0 {
+            remainder ^= 0b101_0011_0111 << (i - 10);
+        }
+    }
+    (data | remainder) ^ 0b101_0100_0001_0010
+}
+
+/// The first version whose symbols carry version information.
+const FIRST_VERSION_WITH_VERSION_INFO: u8 = 7;
+
+/// Computes the 18 bit version information for versions 7 and up, as described in section 7.10 of
+/// the spec: the 6 bit version number, followed by 12 BCH(18,6) error correction bits.
+pub(crate) fn version_information(version_num: u8) -> u32 {
+    let data = (version_num as u32) << 12;
+    let mut remainder = data;
+    for i in (12..18).rev() {
+        if remainder & (1 << i) != 0 {
+            remainder ^= 0b1_1111_0010_0101 << (i - 12);
+        }
+    }
+    data | remainder
+}
+
+/// Whether the mask pattern flips the module at these coordinates. Spec: 8.8.1, Table 10
+pub(crate) fn mask_applies(mask: u8, (x, y): Coordinates) -> bool {
+    match mask {
+        0 => (x + y) % 2 == 0,
+        1 => y % 2 == 0,
+        2 => x % 3 == 0,
+        3 => (x + y) % 3 == 0,
+        4 => (y / 2 + x / 3) % 2 == 0,
+        5 => (x * y) % 2 + (x * y) % 3 == 0,
+        6 => ((x * y) % 2 + (x * y) % 3) % 2 == 0,
+        _ => ((x + y) % 2 + (x * y) % 3) % 2 == 0,
+    }
+}
+
 /// A QR code pixel (the spec calls them "modules" for some reason).
 /// White modules are false, black modules are true.
+#[derive(Clone)]
 pub enum Module {
     Unset,
     Data(bool),
@@ -74,6 +299,21 @@ impl Module {
         }
     }
 
+    /// A short name for the kind of module, for logging and debug output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Unset => "unset",
+            Data(_) => "data",
+            Finder(_) => "finder",
+            TimingHorizontal(_) => "timing-h",
+            TimingVertical(_) => "timing-v",
+            Alignment(_) => "alignment",
+            Format(_) => "format",
+            Dark => "dark",
+            Version(_) => "version",
+        }
+    }
+
     fn zig_zag_skipped(&self) -> bool {
         match self {
             Unset | Data(_) => false,
@@ -91,13 +331,13 @@ struct ZigZagScanner<'a> {
 }
 
 impl<'a> ZigZagScanner<'a> {
-    pub fn new(code: &QRCode) -> ZigZagScanner {
+    pub fn new(code: &QRCode) -> ZigZagScanner<'_> {
         let side_length = code.version.modules_per_side();
         ZigZagScanner {
             position: Some((side_length - 1, side_length - 1)),
             upwards: true,
             next_horizontal: true,
-            code: &code,
+            code,
         }
     }
 
@@ -120,15 +360,14 @@ impl<'a> ZigZagScanner<'a> {
                         x -= 2;
                     }
                 }
-            } else {
+            } else if y + 1 < self.code.version.modules_per_side() {
                 y += 1;
-                if y == self.code.version.modules_per_side() - 1 {
-                    self.switch_directions();
-                    match x.checked_sub(2) {
-                        Some(subbed_x) => x = subbed_x,
-                        None => return None,
-                    };
-                }
+            } else {
+                self.switch_directions();
+                match x.checked_sub(2) {
+                    Some(subbed_x) => x = subbed_x,
+                    None => return None,
+                };
             }
         }
 
@@ -166,13 +405,40 @@ impl<'a> Iterator for ZigZagScanner<'a> {
     }
 }
 
+/// Figures collected while generating a code, for logging and reporting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationStats {
+    pub ecl: ErrorCorrectionLevel,
+    /// The mode of the data, or of its first segment if it switches modes.
+    pub encoding: QREncoding,
+    /// The value of the character count indicator: characters for most modes, but bytes after
+    /// any character set conversion in Bytes mode. Added up over all segments of the data.
+    pub character_count: usize,
+    /// Data codewords needed for the mode, character count, data and terminator.
+    pub codewords_before_padding: usize,
+    /// Codewords added to fill the data capacity of the version.
+    pub padding_codewords: usize,
+    pub ec_codewords: usize,
+    pub elapsed: Duration,
+}
+
+#[derive(Clone)]
 pub struct QRCode {
     pub version: &'static Version,
     pub rows: Vec<Vec<Module>>,
+    /// Only set for codes generated from data, e.g. by `create_qr_code`.
+    pub stats: Option<GenerationStats>,
+    /// Marks the data modules filled by padding codewords, indexed the same way as `rows`. Those
+    /// carry no information, so renderers may style them differently.
+    pub padding: BitMatrix,
+    /// The data mask pattern applied to the code, if any.
+    pub mask: Option<u8>,
+    /// The ECL written into the format information along with the mask, if known.
+    ecl: Option<ErrorCorrectionLevel>,
 }
 
 impl QRCode {
-    fn module(&self, (x, y): Coordinates) -> &Module {
+    pub(crate) fn module(&self, (x, y): Coordinates) -> &Module {
         &self.rows[x][y]
     }
 
@@ -180,8 +446,49 @@ impl QRCode {
         self.rows[x][y] = module;
     }
 
-    fn zig_zag_scanner(&self) -> Vec<Coordinates> {
-        ZigZagScanner::new(&self).collect()
+    /// Iterates over every module along with its coordinates, column by column.
+    pub fn modules(&self) -> impl Iterator<Item = (Coordinates, &Module)> {
+        self.rows.iter().enumerate().flat_map(|(x, column)| {
+            column
+                .iter()
+                .enumerate()
+                .map(move |(y, module)| ((x, y), module))
+        })
+    }
+
+    /// Returns the horizontal runs of black modules as the coordinates of their left end and
+    /// their length, row by row.
+    pub(crate) fn dark_runs(&self) -> Vec<(Coordinates, usize)> {
+        let side = self.version.modules_per_side();
+        let mut runs = Vec::new();
+        for y in 0..side {
+            let mut x = 0;
+            while x < side {
+                if !self.module((x, y)).black() {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < side && self.module((x, y)).black() {
+                    x += 1;
+                }
+                runs.push(((start, y), x - start));
+            }
+        }
+        runs
+    }
+
+    pub(crate) fn zig_zag_scanner(&self) -> Vec<Coordinates> {
+        ZigZagScanner::new(self).collect()
+    }
+
+    /// The coordinates data bits are placed at, in order: the first is where the most significant
+    /// bit of the first codeword goes. Only modules that hold data or EC codewords are listed; the
+    /// remainder bits left over at the end of the data region are not.
+    pub fn data_placement_order(&self) -> Vec<Coordinates> {
+        let mut order = self.zig_zag_scanner();
+        order.truncate(self.version.total_codewords() * 8);
+        order
     }
 
     fn insert_timing_bands(&mut self) {
@@ -290,7 +597,7 @@ impl QRCode {
         // second row
         y += 1;
         self.set_module(Alignment(true), (x, y));
-        for i in 1..3 {
+        for i in 1..4 {
             self.set_module(Alignment(false), (x + i, y));
         }
         self.set_module(Alignment(true), (x + 4, y));
@@ -306,7 +613,7 @@ impl QRCode {
         // fourth row
         y += 1;
         self.set_module(Alignment(true), (x, y));
-        for i in 1..3 {
+        for i in 1..4 {
             self.set_module(Alignment(false), (x + i, y));
         }
         self.set_module(Alignment(true), (x + 4, y));
@@ -318,13 +625,23 @@ impl QRCode {
         }
     }
 
+    /// Whether an alignment pattern centered here would overwrite another function pattern. The
+    /// timing patterns are allowed to run through alignment patterns, since their colors agree.
+    fn alignment_pattern_blocked(&self, (center_x, center_y): Coordinates) -> bool {
+        (center_x - 2..=center_x + 2).any(|x| {
+            (center_y - 2..=center_y + 2).any(|y| match self.module((x, y)) {
+                Unset | Data(_) | Alignment(_) | TimingHorizontal(_) | TimingVertical(_) => false,
+                Finder(_) | Format(_) | Dark | Version(_) => true,
+            })
+        })
+    }
+
     fn insert_alignment_patterns(&mut self) {
         let center_coords = alignment_pattern_coordinates(self.version.num);
-        for (x, y) in center_coords {
-            match self.module((x, y)) {
-                Finder(_) => (),
-                _ => self.insert_alignment_pattern(x, y),
-            };
+        for coords in center_coords {
+            if !self.alignment_pattern_blocked(coords) {
+                self.insert_alignment_pattern(coords.0, coords.1);
+            }
         }
     }
 
@@ -334,50 +651,301 @@ impl QRCode {
         // dark module
         self.set_module(Dark, (8, edge - 7));
 
-        // TODO: replace with a scanner iterator?
-        // top left
-        for i in 0..8 {
-            let coords = (i, 8);
-            if let TimingVertical(_) = self.module(coords) {
-                continue;
+        for copy in &format_information_coordinates(self.version.modules_per_side()) {
+            for coords in copy {
+                self.set_module(Format(false), *coords);
             }
-            self.set_module(Format(false), coords)
-        }
-        for i in 0..9 {
-            let coords = (8, i);
-            if let TimingHorizontal(_) = self.module(coords) {
-                continue;
-            }
-            self.set_module(Format(false), coords)
-        }
-
-        // bottom left
-        for i in 0..7 {
-            self.set_module(Format(false), (8, edge - i))
         }
+    }
 
-        // top right
-        for i in 0..7 {
-            self.set_module(Format(false), (edge - i, 8))
+    /// Writes both copies of a 15 bit format string into the format information area.
+    pub(crate) fn insert_format_information(&mut self, bits: u16) {
+        for copy in &format_information_coordinates(self.version.modules_per_side()) {
+            for (i, coords) in copy.iter().enumerate() {
+                self.set_module(Format(bits & (1 << i) != 0), *coords);
+            }
         }
     }
 
+    /// Writes both copies of the version information for versions 7 and up. Spec: 7.10
     fn insert_version_blocks(&mut self) {
-        // TODO
+        if self.version.num < FIRST_VERSION_WITH_VERSION_INFO {
+            return;
+        }
+        let bits = version_information(self.version.num);
+        let side = self.version.modules_per_side();
+        for i in 0..18 {
+            let black = bits & (1 << i) != 0;
+            let (long, short) = (i / 3, side - 11 + i % 3);
+            // top right, then bottom left
+            self.set_module(Version(black), (short, long));
+            self.set_module(Version(black), (long, short));
+        }
     }
 
     fn insert_data(&mut self, data: &QREncodedData) {
         let coords_order = self.zig_zag_scanner();
+        debug_assert!(
+            data.len() + self.version.remainder_bits() as usize <= coords_order.len(),
+            "{} bits don't fit in the {} data modules of version {}",
+            data.len(),
+            coords_order.len(),
+            self.version.num
+        );
         for (coords, bit) in coords_order.iter().zip(data.iter()) {
             self.set_module(Data(*bit), *coords);
         }
     }
 
+    /// Roughly how many modules can be covered, e.g. by a logo, with the code still decoding. Each
+    /// block can correct half as many codewords as it has EC codewords. Returns 0 for codes built
+    /// without generation stats.
+    pub fn max_obscurable_modules(&self) -> usize {
+        match &self.stats {
+            Some(stats) => obscurable_modules(self.version.values_at_ecl(&stats.ecl)),
+            None => 0,
+        }
+    }
+
+    /// How many characters (or bytes, in Bytes mode) the code holds, as written in its character
+    /// count indicator. Returns 0 for codes built without generation stats.
+    pub fn payload_length(&self) -> usize {
+        self.stats.as_ref().map_or(0, |stats| stats.character_count)
+    }
+
+    /// The mode the data was encoded in, if the code was generated from data.
+    pub fn payload_mode(&self) -> Option<QREncoding> {
+        self.stats.as_ref().map(|stats| stats.encoding)
+    }
+
+    /// A suggested scanning distance for the code printed with modules of this size, using the
+    /// common rule of thumb of 10 times the symbol's width. The quiet zone isn't counted.
+    pub fn recommended_scan_distance_mm(&self, module_size_mm: f32) -> f32 {
+        SCAN_DISTANCE_RATIO * self.version.modules_per_side() as f32 * module_size_mm
+    }
+
+    /// Scores the symbol with the four mask evaluation rules of section 7.8.3 of the spec: runs of
+    /// five or more same colored modules, 2x2 blocks of one color, patterns that look like part
+    /// of a finder, and an imbalance of dark and light modules. Lower is better. Function
+    /// patterns are scored along with the data, as the spec requires.
+    pub fn penalty_score(&self) -> u32 {
+        full_penalty(&self.to_matrix())
+    }
+
+    /// A rough 0-100 estimate of how reliably the code will scan when rendered with these options,
+    /// for comparing configurations. It's a heuristic, not a guarantee. Points come from:
+    /// - the ECL: 10 for Low up to 40 for High, or none for codes built without generation stats;
+    /// - the module size: up to 25, reached at 8 pixels per module;
+    /// - the quiet zone: 5 per module, up to the 4 the spec requires;
+    /// - the penalty score: up to 15, falling to none at an average penalty of 2 per module.
+    pub fn robustness_score(&self, options: &RenderOptions) -> u8 {
+        let ecl_points = match self.stats.as_ref().map(|stats| stats.ecl) {
+            Some(ErrorCorrectionLevel::Low) => 10,
+            Some(ErrorCorrectionLevel::Medium) => 20,
+            Some(ErrorCorrectionLevel::Quartile) => 30,
+            Some(ErrorCorrectionLevel::High) => 40,
+            None => 0,
+        };
+        let (width, height) = options.effective_module_size();
+        let module_points = width.min(height).min(8) * 25 / 8;
+        let quiet_zone_points = options.quiet_zone.min(4) * 5;
+        let side = self.version.modules_per_side();
+        let penalty_per_module = self.penalty_score() as f32 / (side * side) as f32;
+        let penalty_points = (15.0 * (2.0 - penalty_per_module) / 1.5).clamp(0.0, 15.0) as u32;
+        (ecl_points + module_points + quiet_zone_points + penalty_points) as u8
+    }
+
+    /// Flips every data module the mask pattern applies to, leaving function patterns alone.
+    /// Applying the same mask again undoes it. This doesn't change `mask` or the format
+    /// information; `set_mask` keeps those in step.
+    pub fn apply_mask(&mut self, mask: u8) {
+        for (x, column) in self.rows.iter_mut().enumerate() {
+            for (y, module) in column.iter_mut().enumerate() {
+                if let Data(black) = module {
+                    if mask_applies(mask, (x, y)) {
+                        *black = !*black;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Switches the code to the mask, undoing any current one, and writes the matching format
+    /// information if the ECL is known.
+    pub fn set_mask(&mut self, mask: u8) {
+        if let Some(current) = self.mask {
+            self.apply_mask(current);
+        }
+        self.apply_mask(mask);
+        self.mask = Some(mask);
+        if let Some(ecl) = &self.ecl {
+            self.insert_format_information(format_information(ecl, mask));
+        }
+    }
+
+    /// The modules the code would have with the mask in place of its current one, including the
+    /// matching format information if the ECL is known.
+    fn masked_matrix(&self, mask: u8) -> BitMatrix {
+        let mut matrix = self.to_matrix();
+        for ((x, y), module) in self.modules() {
+            if let Data(_) = module {
+                let current = self
+                    .mask
+                    .is_some_and(|current| mask_applies(current, (x, y)));
+                if mask_applies(mask, (x, y)) != current {
+                    matrix[x][y] = !matrix[x][y];
+                }
+            }
+        }
+        if let Some(ecl) = &self.ecl {
+            let bits = format_information(ecl, mask);
+            for copy in &format_information_coordinates(matrix.len()) {
+                for (i, &(x, y)) in copy.iter().enumerate() {
+                    matrix[x][y] = bits & (1 << i) != 0;
+                }
+            }
+        }
+        matrix
+    }
+
+    /// Picks the mask that leaves the code easiest to scan, judged by the selection's scoring.
+    /// Ties go to the lowest numbered mask.
+    pub fn select_mask(&self, selection: MaskSelection) -> u8 {
+        let score = match selection {
+            MaskSelection::Full => full_penalty,
+            MaskSelection::Fast => fast_penalty,
+            MaskSelection::MaxEntropy => return self.select_noisiest_mask(),
+            MaskSelection::Fixed(mask) => return mask,
+        };
+        (0..8)
+            .min_by_key(|&mask| score(&self.masked_matrix(mask)))
+            .unwrap()
+    }
+
+    fn select_noisiest_mask(&self) -> u8 {
+        let candidates: Vec<(u8, BitMatrix)> = (0..8)
+            .map(|mask| (mask, self.masked_matrix(mask)))
+            .collect();
+        let penalties: Vec<u32> = candidates
+            .iter()
+            .map(|(_, matrix)| full_penalty(matrix))
+            .collect();
+        let best = *penalties.iter().min().unwrap();
+        let limit = best + best / MAX_ENTROPY_PENALTY_SLACK;
+        candidates
+            .iter()
+            .zip(&penalties)
+            .filter(|(_, &penalty)| penalty <= limit)
+            // max_by_key keeps the last of equals, so reverse to let ties go to the lowest mask
+            .rev()
+            .max_by_key(|((_, matrix), _)| transition_count(matrix))
+            .map(|((mask, _), _)| *mask)
+            .unwrap()
+    }
+
+    /// Renders a copy of the code with each of the eight masks applied, in order, for comparing
+    /// them side by side.
+    pub fn render_all_masks(&self, options: &RenderOptions) -> [RgbImage; 8] {
+        std::array::from_fn(|mask| {
+            let mut masked = self.clone();
+            masked.set_mask(mask as u8);
+            render(&masked, options)
+        })
+    }
+
+    /// Whether the module at these coordinates holds part of a padding codeword.
+    pub fn is_padding(&self, (x, y): Coordinates) -> bool {
+        self.padding[x][y]
+    }
+
+    /// Marks the modules of the codewords at these positions in the final, interleaved sequence
+    /// as padding.
+    pub(crate) fn mark_padding_codewords(&mut self, positions: &[usize]) {
+        let coords_order = self.zig_zag_scanner();
+        for position in positions {
+            for &(x, y) in &coords_order[position * 8..(position + 1) * 8] {
+                self.padding[x][y] = true;
+            }
+        }
+    }
+
+    /// Returns the black modules as rectangles for plotters and engravers, with horizontally
+    /// adjacent modules merged into a single stroke. Coordinates start at the top left corner of
+    /// the symbol, without a quiet zone.
+    pub fn to_draw_commands(&self, module_size: f32) -> Vec<DrawCommand> {
+        draw_commands(self, module_size)
+    }
+
     pub fn save(&self, path: &Path) -> Result<(), Error> {
         save_qrcode(self, path)
     }
 
-    pub fn new(version: &'static Version, bitstream: QREncodedData) -> QRCode {
+    /// Returns the code as a matrix of booleans (true for black), indexed the same way as `rows`.
+    pub fn to_matrix(&self) -> BitMatrix {
+        self.rows
+            .iter()
+            .map(|row| row.iter().map(Module::black).collect())
+            .collect()
+    }
+
+    /// Lists every module as (x, y, black), row by row from the top left, for placing blocks in
+    /// voxel games or cells in spreadsheets. The quiet zone isn't included, so leave at least 4
+    /// light blocks around the code for it to scan.
+    pub fn to_block_map(&self) -> Vec<(usize, usize, bool)> {
+        let side = self.version.modules_per_side();
+        let mut blocks = Vec::with_capacity(side * side);
+        for y in 0..side {
+            for x in 0..side {
+                blocks.push((x, y, self.rows[x][y].black()));
+            }
+        }
+        blocks
+    }
+
+    /// Returns the code as a fixed-size array of booleans (true for black), indexed the same way
+    /// as `rows`, without allocating. Fails unless the code is exactly `N` modules per side.
+    #[cfg(feature = "fixed-matrix")]
+    pub fn to_fixed_matrix<const N: usize>(&self) -> Result<[[bool; N]; N], Error> {
+        let side = self.version.modules_per_side();
+        if side != N {
+            return Err(format!(
+                "Version {} is {} modules per side, not {}",
+                self.version.num, side, N
+            )
+            .into());
+        }
+        Ok(std::array::from_fn(|x| {
+            std::array::from_fn(|y| self.rows[x][y].black())
+        }))
+    }
+
+    /// Returns the code as a grayscale array for image processing pipelines, indexed by row and
+    /// then column like an image, with 0 for black modules and 255 for white. There's no quiet
+    /// zone.
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray(&self) -> ndarray::Array2<u8> {
+        let side = self.version.modules_per_side();
+        ndarray::Array2::from_shape_fn(
+            (side, side),
+            |(y, x)| {
+                if self.rows[x][y].black() {
+                    0
+                } else {
+                    255
+                }
+            },
+        )
+    }
+
+    /// Builds a code with all the function patterns in place, but no data. They only depend on
+    /// the version, so each version's layout is built once and copied after that.
+    pub(crate) fn function_patterns(version: &'static Version) -> QRCode {
+        FUNCTION_PATTERNS[version.num as usize - 1]
+            .get_or_init(|| QRCode::layout_function_patterns(version))
+            .clone()
+    }
+
+    fn layout_function_patterns(version: &'static Version) -> QRCode {
         let per_side = version.modules_per_side();
         let mut rows = Vec::with_capacity(per_side);
         rows.resize_with(per_side, || {
@@ -385,17 +953,103 @@ impl QRCode {
             row.resize_with(per_side, || Unset);
             row
         });
-        let mut code = QRCode { version, rows };
+        let mut code = QRCode {
+            version,
+            rows,
+            stats: None,
+            padding: vec![vec![false; per_side]; per_side],
+            mask: None,
+            ecl: None,
+        };
         code.insert_finders();
         code.insert_timing_bands();
-        code.insert_alignment_patterns();
         code.insert_format_and_dark();
         code.insert_version_blocks();
+        // last, so it can tell which areas are already taken
+        code.insert_alignment_patterns();
+        code
+    }
+
+    /// Lays out the data and EC codewords in a code of the version and masks it with whichever of
+    /// the eight masks scores the lowest `penalty_score`, writing the format information for the
+    /// ECL and that mask.
+    pub fn new(
+        version: &'static Version,
+        ecl: ErrorCorrectionLevel,
+        bitstream: QREncodedData,
+    ) -> QRCode {
+        let mut code = QRCode::unmasked(version, ecl, bitstream);
+        code.set_mask(code.select_mask(MaskSelection::Full));
+        code
+    }
+
+    /// Lays out the codewords like `new`, but masks them with the given mask instead of picking
+    /// one, e.g. to reproduce a reference code exactly. The format information carries the ECL
+    /// and the pinned mask. Masks run from 0 to 7.
+    pub fn with_mask(
+        version: &'static Version,
+        ecl: ErrorCorrectionLevel,
+        bitstream: QREncodedData,
+        mask: u8,
+    ) -> Result<QRCode, Error> {
+        if mask >= 8 {
+            return Err(format!("Invalid mask {}; masks run from 0 to 7", mask).into());
+        }
+        let mut code = QRCode::unmasked(version, ecl, bitstream);
+        code.set_mask(mask);
+        Ok(code)
+    }
+
+    /// Lays out the codewords without masking them, for callers that pick the mask themselves.
+    /// Setting the mask writes the format information for the ECL.
+    pub(crate) fn unmasked(
+        version: &'static Version,
+        ecl: ErrorCorrectionLevel,
+        bitstream: QREncodedData,
+    ) -> QRCode {
+        let mut code = QRCode::function_patterns(version);
         code.insert_data(&bitstream);
+        code.ecl = Some(ecl);
         code
     }
 }
 
+/// The function pattern layout of each version, from `QRCode::function_patterns`.
+static FUNCTION_PATTERNS: [OnceLock<QRCode>; 40] = [const { OnceLock::new() }; 40];
+
+/// Quiet zone width, in modules, around the text form of a code. Smaller than the spec's 4 to keep
+/// debugging output compact.
+const DISPLAY_QUIET_ZONE: usize = 1;
+
+/// Prints the code with Unicode half blocks, two rows of modules per line, black modules drawn as
+/// filled. Meant for eyeballing codes in tests and REPLs; it scans best in a terminal with dark
+/// text on a light background.
+impl std::fmt::Display for QRCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let side = self.version.modules_per_side();
+        let padded = side + 2 * DISPLAY_QUIET_ZONE;
+        let black = |x: usize, y: usize| {
+            let inside = DISPLAY_QUIET_ZONE..DISPLAY_QUIET_ZONE + side;
+            inside.contains(&x)
+                && inside.contains(&y)
+                && self.rows[x - DISPLAY_QUIET_ZONE][y - DISPLAY_QUIET_ZONE].black()
+        };
+        for top in (0..padded).step_by(2) {
+            for x in 0..padded {
+                let block = match (black(x, top), black(x, top + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                };
+                write!(f, "{}", block)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -418,6 +1072,259 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_alignment_patterns_version_7() {
+        let code = QRCode::function_patterns(Version::by_num(7));
+        let side = code.version.modules_per_side();
+        let count = |kind: &str| code.modules().filter(|(_, m)| m.kind() == kind).count();
+        // 9 possible centers, 3 of which sit on finders
+        assert_eq!(count("alignment"), 6 * 25);
+        assert_eq!(count("version"), 36);
+        for i in 0..6 {
+            for j in 0..3 {
+                assert_eq!(code.module((side - 11 + j, i)).kind(), "version");
+                assert_eq!(code.module((i, side - 11 + j)).kind(), "version");
+            }
+        }
+        // the timing patterns run through the alignment patterns on row and column 6
+        assert_eq!(code.module((22, 4)).kind(), "alignment");
+        assert!(code.module((22, 6)).black());
+        assert!(!code.module((21, 6)).black());
+    }
+
+    #[test]
+    fn test_alignment_patterns_avoid_function_patterns() {
+        for num in 2..=40 {
+            let code = QRCode::function_patterns(Version::by_num(num));
+            let centers = alignment_pattern_coordinates(num as u8);
+            let placed = centers
+                .iter()
+                .filter(|&&coords| matches!(code.module(coords), Alignment(true)))
+                .count();
+            assert_eq!(placed, centers.len() - 3, "version {}", num);
+        }
+    }
+
+    #[test]
+    fn test_data_region_capacity() {
+        // all the codewords plus the remainder bits must exactly fill the data region, or the
+        // capacity tables and the function pattern layout disagree
+        for num in 1..=40 {
+            let version = Version::by_num(num);
+            let data_modules = QRCode::function_patterns(version).zig_zag_scanner().len();
+            for ecl in &[
+                ErrorCorrectionLevel::Low,
+                ErrorCorrectionLevel::Medium,
+                ErrorCorrectionLevel::Quartile,
+                ErrorCorrectionLevel::High,
+            ] {
+                let ecl_data = version.values_at_ecl(ecl);
+                let codewords = ecl_data.data_codewords + ecl_data.total_ec_codewords();
+                assert_eq!(
+                    codewords * 8 + version.remainder_bits() as usize,
+                    data_modules,
+                    "version {} at {:?}",
+                    num,
+                    ecl
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_mask() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let mut masked = code.clone();
+        masked.apply_mask(0);
+        for ((x, y), module) in code.modules() {
+            let flipped = module.black() != masked.module((x, y)).black();
+            let expected = matches!(module, Data(_)) && (x + y) % 2 == 0;
+            assert_eq!(flipped, expected, "({}, {})", x, y);
+        }
+        masked.apply_mask(0);
+        assert_eq!(masked.to_matrix(), code.to_matrix());
+    }
+
+    #[test]
+    fn test_recommended_scan_distance() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        assert_eq!(code.version.num, 1);
+        assert_eq!(code.recommended_scan_distance_mm(1.0), 210.0);
+        assert_eq!(code.recommended_scan_distance_mm(0.5), 105.0);
+    }
+
+    #[test]
+    fn test_cached_function_patterns() {
+        for num in 1..=40 {
+            let version = Version::by_num(num);
+            let kinds = |code: &QRCode| {
+                code.modules()
+                    .map(|(_, module)| (module.kind(), module.black()))
+                    .collect::<Vec<_>>()
+            };
+            let fresh = QRCode::layout_function_patterns(version);
+            let cached = QRCode::function_patterns(version);
+            assert_eq!(kinds(&cached), kinds(&fresh), "version {}", num);
+            assert_eq!(kinds(&QRCode::function_patterns(version)), kinds(&fresh));
+        }
+    }
+
+    #[test]
+    fn test_with_mask() {
+        let version = Version::by_num(1);
+        let ecl = ErrorCorrectionLevel::Medium;
+        let bitstream = || {
+            let codewords = QRBitstreamEncoder::new("HELLO WORLD")
+                .codewords(version, &ecl)
+                .unwrap();
+            crate::qr::error_correction::bitstream_with_ec(codewords, version.values_at_ecl(&ecl))
+                .unwrap()
+        };
+        let unmasked = QRCode::unmasked(version, ecl, bitstream());
+        for mask in 0..8 {
+            let code = QRCode::with_mask(version, ecl, bitstream(), mask).unwrap();
+            assert_eq!(code.mask, Some(mask));
+            assert_eq!(code.to_matrix(), unmasked.masked_matrix(mask));
+            // both copies of the format information carry the pinned mask
+            let matrix = code.to_matrix();
+            let bits = format_information(&ecl, mask);
+            for copy in &format_information_coordinates(matrix.len()) {
+                for (i, &(x, y)) in copy.iter().enumerate() {
+                    assert_eq!(matrix[x][y], bits & (1 << i) != 0, "mask {}", mask);
+                }
+            }
+        }
+        assert!(QRCode::with_mask(version, ecl, bitstream(), 8).is_err());
+    }
+
+    #[test]
+    fn test_new_selects_mask() {
+        let version = Version::by_num(1);
+        let ecl = ErrorCorrectionLevel::Medium;
+        let bitstream = || {
+            let codewords = QRBitstreamEncoder::new("HELLO WORLD")
+                .codewords(version, &ecl)
+                .unwrap();
+            crate::qr::error_correction::bitstream_with_ec(codewords, version.values_at_ecl(&ecl))
+                .unwrap()
+        };
+        let code = QRCode::new(version, ecl, bitstream());
+        let unmasked = QRCode::unmasked(version, ecl, bitstream());
+        let scores: Vec<u32> = (0..8)
+            .map(|mask| full_penalty(&unmasked.masked_matrix(mask)))
+            .collect();
+        let best = scores
+            .iter()
+            .position(|score| score == scores.iter().min().unwrap());
+        assert_eq!(code.mask, best.map(|mask| mask as u8));
+        assert_eq!(code.penalty_score(), *scores.iter().min().unwrap());
+        assert_eq!(code.mask, Some(0));
+        assert_eq!(
+            code.to_matrix(),
+            crate::create_qr_code("HELLO WORLD", ecl)
+                .unwrap()
+                .to_matrix()
+        );
+    }
+
+    #[test]
+    fn test_apply_mask_involution() {
+        let code =
+            crate::create_qr_code("https://example.com/", ErrorCorrectionLevel::High).unwrap();
+        for mask in 0..8 {
+            let mut masked = code.clone();
+            masked.apply_mask(mask);
+            for ((x, y), module) in code.modules() {
+                if !matches!(module, Data(_)) {
+                    assert!(
+                        masked.module((x, y)).black() == module.black(),
+                        "mask {} at ({}, {})",
+                        mask,
+                        x,
+                        y
+                    );
+                }
+            }
+            masked.apply_mask(mask);
+            assert_eq!(masked.to_matrix(), code.to_matrix(), "mask {}", mask);
+        }
+    }
+
+    #[test]
+    fn test_render_all_masks() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let images = code.render_all_masks(&RenderOptions::default());
+        for (i, first) in images.iter().enumerate() {
+            for second in &images[i + 1..] {
+                assert_ne!(first, second);
+            }
+        }
+        let own_mask = code.mask.unwrap() as usize;
+        assert!(images[own_mask] == render(&code, &RenderOptions::default()));
+    }
+
+    #[test]
+    fn test_display() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let text = format!("{}", code);
+        // 21 modules and a quiet zone of 1 on each side, two rows to a line
+        assert_eq!(text.lines().count(), 12);
+        assert!(text.lines().all(|line| line.chars().count() == 23));
+        // the top left finder's top edge sits under the quiet zone row
+        assert!(text.lines().next().unwrap().starts_with(" ▄▄▄▄▄▄▄ "));
+    }
+
+    #[test]
+    fn test_set_mask() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let mut switched = code.clone();
+        switched.set_mask(5);
+        assert_eq!(switched.to_matrix(), code.masked_matrix(5));
+        switched.set_mask(code.mask.unwrap());
+        assert_eq!(switched.to_matrix(), code.to_matrix());
+    }
+
+    #[test]
+    fn test_max_entropy_mask_selection() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let chosen = code.select_mask(MaskSelection::MaxEntropy);
+        let best = full_penalty(&code.masked_matrix(code.select_mask(MaskSelection::Full)));
+        let chosen_matrix = code.masked_matrix(chosen);
+        assert!(full_penalty(&chosen_matrix) <= best + best / MAX_ENTROPY_PENALTY_SLACK);
+        for mask in 0..8 {
+            let matrix = code.masked_matrix(mask);
+            if full_penalty(&matrix) <= best + best / MAX_ENTROPY_PENALTY_SLACK {
+                assert!(transition_count(&matrix) <= transition_count(&chosen_matrix));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fast_mask_selection() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let scored = |selection| {
+            MODULES_SCORED.with(|count| count.set(0));
+            code.select_mask(selection);
+            MODULES_SCORED.with(|count| count.get())
+        };
+        let modules = 21 * 21;
+        // per mask, the full score reads every row and column for both rules 1 and 3, then the
+        // whole symbol for rule 2 and again for rule 4; the fast one reads the columns and the
+        // balance once each
+        assert_eq!(scored(MaskSelection::Full), 8 * 6 * modules);
+        assert_eq!(scored(MaskSelection::Fast), 8 * 2 * modules);
+    }
+
+    #[test]
+    fn test_module_kind() {
+        assert_eq!(Data(true).kind(), "data");
+        assert_eq!(Finder(false).kind(), "finder");
+        assert_eq!(TimingHorizontal(true).kind(), "timing-h");
+        assert_eq!(TimingVertical(true).kind(), "timing-v");
+        assert_eq!(Dark.kind(), "dark");
+        assert_eq!(Unset.kind(), "unset");
+    }
+
     #[test]
     fn test_alignment_pattern_coordinates() {
         assert_eq!(alignment_pattern_coordinates(1), Vec::<Coordinates>::new());
@@ -441,11 +1348,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_data_placement_order() {
+        let code = crate::create_qr_code("Hello, world!", ErrorCorrectionLevel::Low).unwrap();
+        assert_eq!(code.version.num, 1);
+        // no remainder bits at version 1, so every scanned module holds data
+        assert_eq!(code.data_placement_order(), code.zig_zag_scanner());
+        assert_eq!(code.data_placement_order().len(), 26 * 8);
+
+        let version_2 = QRCode::function_patterns(Version::by_num(2));
+        let order = version_2.data_placement_order();
+        assert_eq!(order.len(), 44 * 8);
+        assert_eq!(order.len() + 7, version_2.zig_zag_scanner().len());
+        assert_eq!(order[..], version_2.zig_zag_scanner()[..order.len()]);
+    }
+
     #[test]
     #[rustfmt::skip]
     fn test_zig_zag_scan_version_1() {
         let code = QRCode::new(
             Version::by_num(1),
+            ErrorCorrectionLevel::Low,
             QRBitstreamEncoder::new("Hello, world!")
                 .bitstream(Version::by_num(1), &ErrorCorrectionLevel::Low)
                 .expect("WTFUX"),
@@ -457,25 +1380,171 @@ mod tests {
             (20, 12), (19, 12), (20, 11), (19, 11), (20, 10), (19, 10), (20, 9), (19, 9), (18, 9),
             (17, 9), (18, 10), (17, 10), (18, 11), (17, 11), (18, 12), (17, 12), (18, 13), (17, 13),
             (18, 14), (17, 14), (18, 15), (17, 15), (18, 16), (17, 16), (18, 17), (17, 17),
-            (18, 18), (17, 18), (18, 19), (17, 19), (16, 20), (15, 20), (16, 19), (15, 19),
-            (16, 18), (15, 18), (16, 17), (15, 17), (16, 16), (15, 16), (16, 15), (15, 15),
-            (16, 14), (15, 14), (16, 13), (15, 13), (16, 12), (15, 12), (16, 11), (15, 11),
-            (16, 10), (15, 10), (16, 9), (15, 9), (13, 8), (14, 9), (13, 9), (14, 10), (13, 10),
-            (14, 11), (13, 11), (14, 12), (13, 12), (14, 13), (13, 13), (14, 14), (13, 14),
-            (14, 15), (13, 15), (14, 16), (13, 16), (14, 17), (13, 17), (14, 18), (13, 18),
-            (14, 19), (13, 19), (12, 20), (11, 20), (12, 19), (11, 19), (12, 18), (11, 18),
-            (12, 17), (11, 17), (12, 16), (11, 16), (12, 15), (11, 15), (12, 14), (11, 14),
-            (12, 13), (11, 13), (12, 12), (11, 12), (12, 11), (11, 11), (12, 10), (11, 10), (12, 9),
-            (11, 9), (12, 8), (11, 8), (12, 7), (11, 7), (12, 5), (11, 5), (12, 4), (11, 4),
-            (12, 3), (11, 3), (12, 2), (11, 2), (12, 1), (11, 1), (12, 0), (11, 0), (10, 0), (9, 0),
-            (10, 1), (9, 1), (10, 2), (9, 2), (10, 3), (9, 3), (10, 4), (9, 4), (10, 5), (9, 5),
-            (10, 7), (9, 7), (10, 8), (9, 8), (10, 9), (9, 9), (10, 10), (9, 10), (10, 11), (9, 11),
-            (10, 12), (9, 12), (10, 13), (9, 13), (10, 14), (9, 14), (10, 15), (9, 15), (10, 16),
-            (9, 16), (10, 17), (9, 17), (10, 18), (9, 18), (10, 19), (9, 19), (8, 12), (7, 12),
-            (8, 11), (7, 11), (8, 10), (7, 10), (8, 9), (7, 9), (5, 9), (4, 9), (5, 10), (4, 10),
-            (5, 11), (4, 11), (5, 12), (4, 12), (3, 12), (2, 12), (3, 11), (2, 11), (3, 10),
-            (2, 10), (3, 9), (2, 9), (1, 9), (0, 9), (1, 10), (0, 10), (1, 11), (0, 11), (1, 12),
-            (0, 12)
+            (18, 18), (17, 18), (18, 19), (17, 19), (18, 20), (17, 20), (16, 20), (15, 20),
+            (16, 19), (15, 19), (16, 18), (15, 18), (16, 17), (15, 17), (16, 16), (15, 16),
+            (16, 15), (15, 15), (16, 14), (15, 14), (16, 13), (15, 13), (16, 12), (15, 12),
+            (16, 11), (15, 11), (16, 10), (15, 10), (16, 9), (15, 9), (14, 9), (13, 9),
+            (14, 10), (13, 10), (14, 11), (13, 11), (14, 12), (13, 12), (14, 13), (13, 13),
+            (14, 14), (13, 14), (14, 15), (13, 15), (14, 16), (13, 16), (14, 17), (13, 17),
+            (14, 18), (13, 18), (14, 19), (13, 19), (14, 20), (13, 20), (12, 20), (11, 20),
+            (12, 19), (11, 19), (12, 18), (11, 18), (12, 17), (11, 17), (12, 16), (11, 16),
+            (12, 15), (11, 15), (12, 14), (11, 14), (12, 13), (11, 13), (12, 12), (11, 12),
+            (12, 11), (11, 11), (12, 10), (11, 10), (12, 9), (11, 9), (12, 8), (11, 8), (12, 7),
+            (11, 7), (12, 5), (11, 5), (12, 4), (11, 4), (12, 3), (11, 3), (12, 2), (11, 2),
+            (12, 1), (11, 1), (12, 0), (11, 0), (10, 0), (9, 0), (10, 1), (9, 1), (10, 2), (9, 2),
+            (10, 3), (9, 3), (10, 4), (9, 4), (10, 5), (9, 5), (10, 7), (9, 7), (10, 8), (9, 8),
+            (10, 9), (9, 9), (10, 10), (9, 10), (10, 11), (9, 11), (10, 12), (9, 12), (10, 13),
+            (9, 13), (10, 14), (9, 14), (10, 15), (9, 15), (10, 16), (9, 16), (10, 17), (9, 17),
+            (10, 18), (9, 18), (10, 19), (9, 19), (10, 20), (9, 20), (8, 12), (7, 12), (8, 11),
+            (7, 11), (8, 10), (7, 10), (8, 9), (7, 9), (5, 9), (4, 9), (5, 10), (4, 10), (5, 11),
+            (4, 11), (5, 12), (4, 12), (3, 12), (2, 12), (3, 11), (2, 11), (3, 10), (2, 10), (3, 9),
+            (2, 9), (1, 9), (0, 9), (1, 10), (0, 10), (1, 11), (0, 11), (1, 12), (0, 12)
         ]);
     }
+
+    #[test]
+    fn test_to_block_map() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let blocks = code.to_block_map();
+        assert_eq!(blocks.len(), 21 * 21);
+        let black_modules = code.modules().filter(|(_, module)| module.black()).count();
+        assert_eq!(blocks.iter().filter(|block| block.2).count(), black_modules);
+        // inside the top left finder's white ring
+        assert_eq!(blocks[22], (1, 1, false));
+    }
+
+    #[test]
+    #[cfg(feature = "fixed-matrix")]
+    fn test_to_fixed_matrix() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let matrix = code.to_fixed_matrix::<21>().unwrap();
+        for (x, column) in code.to_matrix().iter().enumerate() {
+            assert_eq!(&matrix[x][..], &column[..]);
+        }
+        assert!(code.to_fixed_matrix::<25>().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "ndarray")]
+    fn test_to_ndarray() {
+        let code = crate::create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Quartile).unwrap();
+        let array = code.to_ndarray();
+        assert_eq!(array.dim(), (21, 21));
+        assert!(array.iter().all(|&value| value == 0 || value == 255));
+        // the timing pattern alternates along row 6
+        assert_eq!(array[[6, 8]], 0);
+        assert_eq!(array[[6, 9]], 255);
+    }
+
+    #[test]
+    fn test_format_information() {
+        // Spec: Annex C, Table C.1, by ECL and then mask
+        let table: [(ErrorCorrectionLevel, [u16; 8]); 4] = [
+            (
+                ErrorCorrectionLevel::Low,
+                [
+                    0x77C4, 0x72F3, 0x7DAA, 0x789D, 0x662F, 0x6318, 0x6C41, 0x6976,
+                ],
+            ),
+            (
+                ErrorCorrectionLevel::Medium,
+                [
+                    0x5412, 0x5125, 0x5E7C, 0x5B4B, 0x45F9, 0x40CE, 0x4F97, 0x4AA0,
+                ],
+            ),
+            (
+                ErrorCorrectionLevel::Quartile,
+                [
+                    0x355F, 0x3068, 0x3F31, 0x3A06, 0x24B4, 0x2183, 0x2EDA, 0x2BED,
+                ],
+            ),
+            (
+                ErrorCorrectionLevel::High,
+                [
+                    0x1689, 0x13BE, 0x1CE7, 0x19D0, 0x0762, 0x0255, 0x0D0C, 0x083B,
+                ],
+            ),
+        ];
+        for (ecl, values) in &table {
+            for (mask, &expected) in values.iter().enumerate() {
+                assert_eq!(format_information(ecl, mask as u8), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_version_information() {
+        // Spec: Annex D, Table D.1
+        assert_eq!(version_information(7), 0x07C94);
+        assert_eq!(version_information(8), 0x085BC);
+        assert_eq!(version_information(21), 0x15683);
+        assert_eq!(version_information(40), 0x28C69);
+    }
+
+    #[test]
+    fn test_version_blocks() {
+        let code = QRCode::function_patterns(Version::by_num(7));
+        let side = code.version.modules_per_side();
+        // 0x07C94 = 000111 110010 010100, least significant bit first
+        let bits: Vec<bool> = (0..18)
+            .map(|i| code.rows[i / 3][side - 11 + i % 3].black())
+            .collect();
+        let transposed: Vec<bool> = (0..18)
+            .map(|i| code.rows[side - 11 + i % 3][i / 3].black())
+            .collect();
+        let expected: Vec<bool> = (0..18).map(|i| 0x07C94 & (1 << i) != 0).collect();
+        assert_eq!(bits, expected);
+        assert_eq!(transposed, expected);
+        assert!(matches!(code.rows[0][side - 11], Version(false)));
+        assert!(matches!(code.rows[5][side - 9], Version(false)));
+        assert!(matches!(code.rows[4][side - 11], Version(true)));
+    }
+
+    #[test]
+    fn test_version_blocks_reserved() {
+        let layout = QRCode::function_patterns(Version::by_num(7));
+        let count = |code: &QRCode, kind: &str| {
+            code.modules()
+                .filter(|(_, module)| module.kind() == kind)
+                .count()
+        };
+        assert_eq!(count(&layout, "version"), 36);
+        // 45x45 modules, less the function patterns, with no remainder bits at version 7
+        let free = layout.zig_zag_scanner();
+        assert_eq!(free.len(), count(&layout, "unset"));
+        assert_eq!(free.len(), 196 * 8);
+        assert_eq!(free.len(), layout.version.total_codewords() * 8);
+        assert!(free
+            .iter()
+            .all(|&(x, y)| !matches!(layout.rows[x][y], Version(_))));
+
+        let code = crate::create_qr_code(&"1".repeat(350), ErrorCorrectionLevel::Low).unwrap();
+        assert_eq!(code.version.num, 7);
+        assert_eq!(count(&code, "data"), free.len());
+        assert_eq!(count(&code, "version"), 36);
+    }
+
+    #[test]
+    fn test_line_penalty() {
+        assert_eq!(line_penalty(&[true; 4]), 0);
+        assert_eq!(line_penalty(&[false; 5]), 3);
+        assert_eq!(line_penalty(&[true; 7]), 5);
+        assert_eq!(line_penalty(&FINDER_LIKE), 40);
+        let mut reversed = FINDER_LIKE;
+        reversed.reverse();
+        assert_eq!(line_penalty(&reversed), 40);
+        let alternating: Vec<bool> = (0..11).map(|i| i % 2 == 0).collect();
+        assert_eq!(line_penalty(&alternating), 0);
+    }
+
+    #[test]
+    fn test_robustness_score() {
+        let data = "https://example.com";
+        let sturdy = crate::create_qr_code(data, ErrorCorrectionLevel::High).unwrap();
+        let fragile = crate::create_qr_code(data, ErrorCorrectionLevel::Low).unwrap();
+        let sturdy_score = sturdy.robustness_score(&RenderOptions::square(10));
+        let fragile_score = fragile.robustness_score(&RenderOptions::square(1));
+        assert!(sturdy_score > fragile_score);
+        assert!(sturdy_score <= 100);
+    }
 }
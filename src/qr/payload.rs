@@ -0,0 +1,249 @@
+use crate::qr::Error;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// RFC 5545 limits content lines to 75 octets, not counting the line break.
+const ICAL_LINE_OCTETS: usize = 75;
+
+/// Formats a time as a UTC iCalendar date-time, e.g. 20201031T183000Z.
+fn ical_date_time(time: SystemTime) -> Result<String, Error> {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| "Event times before 1970 aren't supported")?
+        .as_secs();
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    // Converts days since the epoch to a proleptic Gregorian date, per Howard Hinnant's
+    // days_from_civil algorithm run backwards
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    Ok(format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    ))
+}
+
+/// Escapes the characters iCalendar gives a meaning to in text values.
+fn ical_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(character);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => (),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// Appends a content line, folding it onto continuation lines (which start with a space) so that
+/// none is longer than 75 octets. Lines are only broken between characters.
+fn push_folded(out: &mut String, line: &str) {
+    let mut octets = 0;
+    for character in line.chars() {
+        if octets + character.len_utf8() > ICAL_LINE_OCTETS {
+            out.push_str("\r\n ");
+            octets = 1;
+        }
+        out.push(character);
+        octets += character.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Builds a minimal iCalendar VEVENT, which phone calendar apps offer to add when scanned.
+pub fn event(
+    summary: &str,
+    start: SystemTime,
+    end: SystemTime,
+    location: Option<&str>,
+) -> Result<String, Error> {
+    if end < start {
+        return Err("The event can't end before it starts".into());
+    }
+    let mut out = String::new();
+    push_folded(&mut out, "BEGIN:VEVENT");
+    push_folded(&mut out, &format!("SUMMARY:{}", ical_text(summary)));
+    push_folded(&mut out, &format!("DTSTART:{}", ical_date_time(start)?));
+    push_folded(&mut out, &format!("DTEND:{}", ical_date_time(end)?));
+    if let Some(location) = location {
+        push_folded(&mut out, &format!("LOCATION:{}", ical_text(location)));
+    }
+    push_folded(&mut out, "END:VEVENT");
+    Ok(out)
+}
+
+/// Formats a coordinate to 6 decimal places, about 10cm, without trailing zeros.
+fn geo_coordinate(degrees: f64) -> String {
+    let formatted = format!("{:.6}", degrees);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+/// Builds a geo: URI (RFC 5870) for a point given in degrees, which map apps open when scanned.
+pub fn geo(latitude: f64, longitude: f64) -> Result<String, Error> {
+    if !(-90.0..=90.0).contains(&latitude) {
+        return Err(format!("The latitude {} isn't between -90 and 90", latitude).into());
+    }
+    if !(-180.0..=180.0).contains(&longitude) {
+        return Err(format!("The longitude {} isn't between -180 and 180", longitude).into());
+    }
+    Ok(format!(
+        "geo:{},{}",
+        geo_coordinate(latitude),
+        geo_coordinate(longitude)
+    ))
+}
+
+/// Percent-encodes every byte of the text except unreserved URI characters and `keep`.
+fn percent_encode(text: &str, keep: &[u8]) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for &byte in text.as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) || keep.contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            write!(encoded, "%{:02X}", byte).unwrap();
+        }
+    }
+    encoded
+}
+
+/// Builds a mailto: URI (RFC 6068) for an email with an optional subject and body, which mail
+/// apps open as a draft when scanned.
+pub fn mailto(to: &str, subject: Option<&str>, body: Option<&str>) -> String {
+    let mut uri = format!("mailto:{}", percent_encode(to, b"@"));
+    let fields = [("subject", subject), ("body", body)];
+    let mut separator = '?';
+    for (name, value) in fields.iter() {
+        if let Some(value) = value {
+            // the RFC asks for line breaks in the body to be CRLF
+            let value = value.replace("\r\n", "\n").replace('\n', "\r\n");
+            write!(uri, "{}{}={}", separator, name, percent_encode(&value, b"")).unwrap();
+            separator = '&';
+        }
+    }
+    uri
+}
+
+/// Builds a tel: URI (RFC 3966), which phones offer to call when scanned. Spaces are dropped, and
+/// the number may only contain digits, the visual separators `-`, `.`, `(` and `)`, and a leading
+/// `+` for international numbers.
+pub fn tel(number: &str) -> Result<String, Error> {
+    let number: String = number.chars().filter(|c| *c != ' ').collect();
+    let digits = number.strip_prefix('+').unwrap_or(&number);
+    if !digits.chars().any(|c| c.is_ascii_digit()) {
+        return Err("The phone number has no digits".into());
+    }
+    if let Some(invalid) = digits
+        .chars()
+        .find(|c| !c.is_ascii_digit() && !"-.()".contains(*c))
+    {
+        return Err(format!("The phone number can't contain {:?}", invalid).into());
+    }
+    Ok(format!("tel:{}", number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ical_date_time() {
+        let at = |seconds| ical_date_time(UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+        assert_eq!(at(0), "19700101T000000Z");
+        assert_eq!(at(951_782_400), "20000229T000000Z");
+        assert_eq!(at(1_604_169_000), "20201031T183000Z");
+        assert_eq!(at(4_102_444_799), "20991231T235959Z");
+    }
+
+    #[test]
+    fn test_event() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_604_169_000);
+        let end = start + Duration::from_secs(90 * 60);
+        let payload = event("Party; bring snacks", start, end, Some("Room 101")).unwrap();
+        assert_eq!(
+            payload,
+            "BEGIN:VEVENT\r\nSUMMARY:Party\\; bring snacks\r\nDTSTART:20201031T183000Z\r\n\
+             DTEND:20201031T200000Z\r\nLOCATION:Room 101\r\nEND:VEVENT\r\n"
+        );
+        assert!(event("Backwards", end, start, None).is_err());
+    }
+
+    #[test]
+    fn test_event_folding() {
+        let summary = "é".repeat(50);
+        let payload = event(&summary, UNIX_EPOCH, UNIX_EPOCH, None).unwrap();
+        for line in payload.split("\r\n") {
+            assert!(line.len() <= ICAL_LINE_OCTETS);
+        }
+        let unfolded = payload.replace("\r\n ", "");
+        assert!(unfolded.contains(&format!("SUMMARY:{}\r\n", summary)));
+    }
+
+    #[test]
+    fn test_geo() {
+        assert_eq!(
+            geo(40.689247, -74.044502).unwrap(),
+            "geo:40.689247,-74.044502"
+        );
+        assert_eq!(geo(51.5, 0.0).unwrap(), "geo:51.5,0");
+        assert_eq!(geo(-0.0000001, 180.0).unwrap(), "geo:0,180");
+        assert_eq!(geo(12.345678901, 1.0).unwrap(), "geo:12.345679,1");
+    }
+
+    #[test]
+    fn test_geo_out_of_range() {
+        assert!(geo(90.5, 0.0).is_err());
+        assert!(geo(-91.0, 0.0).is_err());
+        assert!(geo(0.0, 180.1).is_err());
+        assert!(geo(f64::NAN, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_mailto() {
+        assert_eq!(
+            mailto("me@example.com", None, None),
+            "mailto:me@example.com"
+        );
+        assert_eq!(
+            mailto("me@example.com", Some("Fish & chips"), Some("Hi,\nsee you")),
+            "mailto:me@example.com?subject=Fish%20%26%20chips&body=Hi%2C%0D%0Asee%20you"
+        );
+        assert_eq!(
+            mailto("a?b@example.com", None, Some("x")),
+            "mailto:a%3Fb@example.com?body=x"
+        );
+    }
+
+    #[test]
+    fn test_tel() {
+        assert_eq!(tel("+1 (555) 010-9999").unwrap(), "tel:+1(555)010-9999");
+        assert!(tel("555-CALL").is_err());
+        assert!(tel("1+2").is_err());
+        assert!(tel("+").is_err());
+    }
+}
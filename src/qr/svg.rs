@@ -0,0 +1,242 @@
+use crate::qr::image::QUIET_ZONE_MODULES;
+use crate::qr::pattern::{Module, QRCode};
+use std::fmt::Write;
+
+/// The shape each dark module of a styled pattern is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternShape {
+    Square,
+    Circle,
+}
+
+/// Styling for one kind of function pattern. A missing color falls back to the foreground.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternStyle {
+    pub color: Option<String>,
+    pub shape: PatternShape,
+}
+
+impl Default for PatternStyle {
+    fn default() -> Self {
+        PatternStyle {
+            color: None,
+            shape: PatternShape::Square,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgOptions {
+    /// Size of a module in SVG user units.
+    pub module_size: u32,
+    pub foreground: String,
+    pub background: String,
+    pub alignment: PatternStyle,
+    /// Draws horizontally adjacent square modules of the same color as a single rect, which
+    /// makes the output much smaller.
+    pub merge_runs: bool,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            module_size: 4,
+            foreground: "#000000".to_string(),
+            background: "#ffffff".to_string(),
+            alignment: PatternStyle::default(),
+            merge_runs: true,
+        }
+    }
+}
+
+fn module_style<'a>(module: &Module, options: &'a SvgOptions) -> (PatternShape, &'a str) {
+    match module {
+        Module::Alignment(_) => (
+            options.alignment.shape,
+            options
+                .alignment
+                .color
+                .as_ref()
+                .unwrap_or(&options.foreground),
+        ),
+        _ => (PatternShape::Square, &options.foreground),
+    }
+}
+
+/// Writes a run of `length` modules; only squares are ever merged into runs longer than one.
+fn write_run(
+    svg: &mut String,
+    (x, y): (u32, u32),
+    size: u32,
+    length: u32,
+    shape: PatternShape,
+    color: &str,
+) {
+    match shape {
+        PatternShape::Square => writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            x,
+            y,
+            size * length,
+            size,
+            color
+        ),
+        PatternShape::Circle => writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+            x as f32 + size as f32 / 2.0,
+            y as f32 + size as f32 / 2.0,
+            size as f32 / 2.0,
+            color
+        ),
+    }
+    .unwrap();
+}
+
+/// Renders the code as an SVG document, including the quiet zone.
+pub fn to_svg(code: &QRCode, options: &SvgOptions) -> String {
+    let size = options.module_size;
+    let side = (code.version.modules_per_side() as u32 + 2 * QUIET_ZONE_MODULES) * size;
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#,
+        side
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"<rect width="{0}" height="{0}" fill="{1}"/>"#,
+        side, options.background
+    )
+    .unwrap();
+    let per_side = code.version.modules_per_side();
+    for y in 0..per_side {
+        let mut x = 0;
+        while x < per_side {
+            let module = code.module((x, y));
+            if !module.black() {
+                x += 1;
+                continue;
+            }
+            let style = module_style(module, options);
+            let mut length = 1;
+            if options.merge_runs && style.0 == PatternShape::Square {
+                while x + length < per_side && {
+                    let next = code.module((x + length, y));
+                    next.black() && module_style(next, options) == style
+                } {
+                    length += 1;
+                }
+            }
+            let position = (
+                (x as u32 + QUIET_ZONE_MODULES) * size,
+                (y as u32 + QUIET_ZONE_MODULES) * size,
+            );
+            write_run(&mut svg, position, size, length as u32, style.0, style.1);
+            x += length;
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_qr_code;
+    use crate::qr::error_correction::ErrorCorrectionLevel;
+
+    fn module_element(options: &SvgOptions, (x, y): (u32, u32), color: &str) -> String {
+        let size = options.module_size;
+        format!(
+            r#"<rect x="{}" y="{}" width="{2}" height="{2}" fill="{3}"/>"#,
+            (x + QUIET_ZONE_MODULES) * size,
+            (y + QUIET_ZONE_MODULES) * size,
+            size,
+            color
+        )
+    }
+
+    #[test]
+    fn test_default_alignment_uses_foreground() {
+        // version 2 has a single alignment pattern centered on (18, 18)
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::High).unwrap();
+        assert_eq!(code.version.num, 2);
+        let options = SvgOptions {
+            merge_runs: false,
+            ..SvgOptions::default()
+        };
+        let svg = to_svg(&code, &options);
+        assert!(svg.contains(&module_element(&options, (18, 18), "#000000")));
+        assert!(!svg.contains("<circle"));
+    }
+
+    #[test]
+    fn test_alignment_accent_color() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::High).unwrap();
+        let options = SvgOptions {
+            alignment: PatternStyle {
+                color: Some("#ff8800".to_string()),
+                shape: PatternShape::Square,
+            },
+            merge_runs: false,
+            ..SvgOptions::default()
+        };
+        let svg = to_svg(&code, &options);
+        for ((x, y), module) in code.modules() {
+            let (x, y) = (x as u32, y as u32);
+            match module {
+                Module::Alignment(true) => {
+                    assert!(svg.contains(&module_element(&options, (x, y), "#ff8800")))
+                }
+                Module::Alignment(false) => {
+                    assert!(!svg.contains(&module_element(&options, (x, y), "#ff8800")))
+                }
+                module if module.black() => {
+                    assert!(svg.contains(&module_element(&options, (x, y), "#000000")))
+                }
+                _ => (),
+            }
+        }
+        assert_eq!(svg.matches("#ff8800").count(), 17);
+    }
+
+    #[test]
+    fn test_alignment_circles() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::High).unwrap();
+        let options = SvgOptions {
+            alignment: PatternStyle {
+                color: Some("red".to_string()),
+                shape: PatternShape::Circle,
+            },
+            ..SvgOptions::default()
+        };
+        let svg = to_svg(&code, &options);
+        // center of module (18, 18) with the quiet zone and 4 units per module
+        assert!(svg.contains(r#"<circle cx="90" cy="90" r="2" fill="red"/>"#));
+        assert_eq!(svg.matches("<circle").count(), 17);
+    }
+
+    #[test]
+    fn test_merged_runs() {
+        let code = create_qr_code("HELLO WORLD", ErrorCorrectionLevel::Medium).unwrap();
+        let options = SvgOptions::default();
+        let svg = to_svg(&code, &options);
+        // the solid top edge of the first finder is a single rect
+        assert!(svg.contains(r##"<rect x="16" y="16" width="28" height="4" fill="#000000"/>"##));
+        assert!(!svg.contains(r#"<rect x="20" y="16""#));
+
+        let unmerged = to_svg(
+            &code,
+            &SvgOptions {
+                merge_runs: false,
+                ..SvgOptions::default()
+            },
+        );
+        let black_modules = code.modules().filter(|(_, m)| m.black()).count();
+        assert_eq!(unmerged.matches("<rect").count(), black_modules + 1);
+        assert_eq!(svg.matches("<rect").count(), code.dark_runs().len() + 1);
+    }
+}
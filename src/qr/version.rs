@@ -1,4 +1,4 @@
-use crate::qr::encode::QRBitstreamEncoder;
+use crate::qr::encode::{QRBitstreamEncoder, QREncoding};
 use crate::qr::error_correction::ErrorCorrectionLevel;
 use crate::qr::Error;
 
@@ -29,8 +29,19 @@ impl VersionEclData {
         group1 + group2
     }
 
+    /// The number of blocks the codewords are split into for error correction.
+    pub fn total_blocks(&self) -> usize {
+        self.group1.blocks as usize + self.group2.as_ref().map_or(0, |grp| grp.blocks as usize)
+    }
+
+    /// Data and EC codewords together, which fill the version's data region.
+    pub fn total_codewords(&self) -> usize {
+        self.data_codewords + self.total_ec_codewords()
+    }
+
     pub fn max_codewords_per_group(&self) -> usize {
-        (self.group2.as_ref().unwrap_or(&self.group1).codewords).into()
+        let group2_codewords = self.group2.as_ref().map_or(0, |group| group.codewords);
+        self.group1.codewords.max(group2_codewords).into()
     }
 }
 
@@ -64,6 +75,38 @@ impl Version {
         self.values_at_ecl(ecl).data_codewords
     }
 
+    /// How much data of a single mode fits in the version at the ECL: digits in Numeric mode,
+    /// characters in Alphanumeric and Kanji modes, and bytes in Bytes mode. Spec: Table 7
+    pub fn character_capacity(&self, ecl: &ErrorCorrectionLevel, mode: QREncoding) -> usize {
+        let count_bits = mode.character_count_bits(self.num);
+        // the bits left after the mode and character count indicators
+        let bits = self.codeword_count(ecl) * 8 - 4 - count_bits;
+        let capacity = match mode {
+            QREncoding::Numeric => 3 * (bits / 10) + [0, 0, 0, 0, 1, 1, 1, 2, 2, 2][bits % 10],
+            QREncoding::Alphanumeric => 2 * (bits / 11) + (bits % 11 >= 6) as usize,
+            QREncoding::Bytes => bits / 8,
+            QREncoding::Kanji => bits / 13,
+        };
+        capacity.min((1 << count_bits) - 1)
+    }
+
+    /// Returns the number of data and EC codewords the version holds, which is the same at every
+    /// ECL. Spec: Table 1
+    pub fn total_codewords(&self) -> usize {
+        self.l_data.total_codewords()
+    }
+
+    /// Returns the number of zero bits left over in the data region after all the codewords have
+    /// been placed. Spec: Table 1
+    pub fn remainder_bits(&self) -> u8 {
+        match self.num {
+            2..=6 => 7,
+            14..=20 | 28..=34 => 3,
+            21..=27 => 4,
+            _ => 0,
+        }
+    }
+
     /// Returns the number of modules on a single side of the finished QR code.
     pub fn modules_per_side(&self) -> usize {
         ((4 * (self.num - 1)) + 21) as usize
@@ -2017,20 +2060,101 @@ const VERSIONS: [&Version; 40] = [
     },
 ];
 
+/// The most characters any input can have: version 40 at Low holds 7089 digits in Numeric mode,
+/// and every character takes at least a byte of the input.
+pub const MAX_INPUT_BYTES: usize = 7089;
+/// The most bytes version 40 at Low holds in Bytes mode.
+pub const MAX_BYTES_MODE_BYTES: usize = 2953;
+
+/// Whether the encoded data fits in the version at this ECL.
+pub(crate) fn fits(
+    encoder: &QRBitstreamEncoder,
+    version: &Version,
+    ecl: &ErrorCorrectionLevel,
+) -> bool {
+    encoder.codeword_count_before_padding(version.num) <= version.codeword_count(ecl)
+}
+
 pub fn choose_version(
     encoder: &QRBitstreamEncoder,
     ecl: &ErrorCorrectionLevel,
 ) -> Result<&'static Version, Error> {
     for version in VERSIONS.iter() {
-        let codewords = encoder.codeword_count_before_padding(version.num);
-        let cap = version.codeword_count(&ecl);
-        if codewords < cap {
+        if fits(encoder, version, ecl) {
             return Ok(version);
         }
     }
     Err("The data is too long for a QR code at that error correction level!".into())
 }
 
+/// Finds the smallest version the data fits in at any of the allowed ECLs, for callers that will
+/// take whichever ECL keeps the code smallest. When several ECLs fit the same version, the
+/// strongest wins.
+pub fn smallest_version_any_ecl(
+    data: &str,
+    allowed: &[ErrorCorrectionLevel],
+) -> Result<(u8, ErrorCorrectionLevel), Error> {
+    if allowed.is_empty() {
+        return Err("No error correction levels were allowed".into());
+    }
+    let encoder = QRBitstreamEncoder::new(data);
+    allowed
+        .iter()
+        .filter_map(|ecl| {
+            choose_version(&encoder, ecl)
+                .ok()
+                .map(|version| (version.num, *ecl))
+        })
+        .min_by_key(|&(num, ecl)| (num, std::cmp::Reverse(ecl)))
+        .ok_or_else(|| {
+            "The data is too long for a QR code at any of the allowed error correction levels"
+                .into()
+        })
+}
+
+/// Returned by `url_fits` when a URL needs a larger version than allowed.
+#[derive(Debug, PartialEq)]
+pub struct UrlTooLong {
+    /// How many characters would have to be removed for the URL to fit.
+    pub overflow: usize,
+}
+
+impl std::fmt::Display for UrlTooLong {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The URL is {} characters too long; consider shortening it",
+            self.overflow
+        )
+    }
+}
+
+fn fits_within(data: &str, ecl: &ErrorCorrectionLevel, max_version: u8) -> bool {
+    choose_version(&QRBitstreamEncoder::new(data), ecl).is_ok_and(|v| v.num <= max_version)
+}
+
+/// Checks whether the URL fits in a code no larger than `max_version`. If it doesn't, the error
+/// reports how many trailing characters would need to be cut for it to fit.
+pub fn url_fits(url: &str, ecl: &ErrorCorrectionLevel, max_version: u8) -> Result<(), UrlTooLong> {
+    if fits_within(url, ecl, max_version) {
+        return Ok(());
+    }
+    let boundaries: Vec<usize> = url.char_indices().map(|(idx, _)| idx).collect();
+    // binary search for the longest prefix that still fits; the empty prefix always does
+    let (mut fitting, mut too_long) = (0, boundaries.len());
+    while too_long - fitting > 1 {
+        let middle = (fitting + too_long) / 2;
+        if fits_within(&url[..boundaries[middle]], ecl, max_version) {
+            fitting = middle;
+        } else {
+            too_long = middle;
+        }
+    }
+    Err(UrlTooLong {
+        overflow: boundaries.len() - fitting,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2051,6 +2175,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_total_codewords() {
+        for ver in VERSIONS.iter() {
+            for ecl in &[&ver.m_data, &ver.q_data, &ver.h_data] {
+                assert_eq!(ecl.total_codewords(), ver.total_codewords());
+            }
+        }
+        assert_eq!(Version::by_num(1).total_codewords(), 26);
+        assert_eq!(Version::by_num(40).total_codewords(), 3706);
+    }
+
+    #[test]
+    fn test_remainder_bits() {
+        for &(num, bits) in &[(1, 0), (2, 7), (14, 3), (21, 4), (28, 3), (35, 0)] {
+            assert_eq!(
+                Version::by_num(num).remainder_bits(),
+                bits,
+                "version {}",
+                num
+            );
+        }
+    }
+
     #[test]
     fn test_modules_per_side() {
         assert_eq!(Version::by_num(1).modules_per_side(), 21);
@@ -2069,6 +2216,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_choose_version_exact_fit() {
+        // 4 + 8 + 17 * 8 bits is exactly the 19 data codewords of version 1 at Low
+        let exact = QRBitstreamEncoder::new("abcdefghijklmnopq");
+        assert_eq!(exact.codeword_count_before_padding(1), 19);
+        let low = ErrorCorrectionLevel::Low;
+        assert_eq!(choose_version(&exact, &low).unwrap().num, 1);
+        let over = QRBitstreamEncoder::new("abcdefghijklmnopqr");
+        assert_eq!(choose_version(&over, &low).unwrap().num, 2);
+        // the same goes for the largest version
+        let largest = QRBitstreamEncoder::new(&"1".repeat(MAX_INPUT_BYTES));
+        assert_eq!(choose_version(&largest, &low).unwrap().num, 40);
+    }
+
     #[test]
     fn test_choose_version_medium() {
         let encoder = QRBitstreamEncoder::new("12300001010ASKOIDGOAS");
@@ -2116,4 +2277,74 @@ mod tests {
             38
         );
     }
+
+    #[test]
+    fn test_smallest_version_any_ecl() {
+        use ErrorCorrectionLevel::*;
+        // version 1 holds 16 alphanumeric characters at Quartile, but only 10 at High
+        assert_eq!(
+            smallest_version_any_ecl("HELLO WORLD", &[Low, Medium, Quartile, High]),
+            Ok((1, Quartile))
+        );
+        assert_eq!(
+            smallest_version_any_ecl("HELLO WORLD", &[High, Low]),
+            Ok((1, Low))
+        );
+        assert_eq!(
+            smallest_version_any_ecl("HELLO WORLD", &[High]),
+            Ok((2, High))
+        );
+        assert!(smallest_version_any_ecl("HELLO WORLD", &[]).is_err());
+        assert!(smallest_version_any_ecl(&"1".repeat(7100), &[Low, High]).is_err());
+    }
+
+    #[test]
+    fn test_character_capacity() {
+        use ErrorCorrectionLevel::*;
+        use QREncoding::*;
+        let version_1 = Version::by_num(1);
+        assert_eq!(version_1.character_capacity(&Low, Numeric), 41);
+        assert_eq!(version_1.character_capacity(&Low, Alphanumeric), 25);
+        assert_eq!(version_1.character_capacity(&Low, Bytes), 17);
+        assert_eq!(version_1.character_capacity(&Low, Kanji), 10);
+        assert_eq!(version_1.character_capacity(&High, Numeric), 17);
+        assert_eq!(
+            Version::by_num(10).character_capacity(&Medium, Alphanumeric),
+            311
+        );
+        let version_40 = Version::by_num(40);
+        assert_eq!(
+            version_40.character_capacity(&Low, Numeric),
+            MAX_INPUT_BYTES
+        );
+        assert_eq!(
+            version_40.character_capacity(&Low, Bytes),
+            MAX_BYTES_MODE_BYTES
+        );
+        assert_eq!(version_40.character_capacity(&High, Alphanumeric), 1852);
+    }
+
+    #[test]
+    fn test_url_fits() {
+        assert_eq!(
+            url_fits("https://example.com", &ErrorCorrectionLevel::Medium, 3),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_url_too_long() {
+        // byte mode at version 3 Medium leaves room for 42 characters
+        let url = "https://example.com/some/rather/long/path/to/a/page?query=1";
+        assert_eq!(url.len(), 59);
+        assert_eq!(
+            url_fits(url, &ErrorCorrectionLevel::Medium, 3),
+            Err(UrlTooLong { overflow: 17 })
+        );
+        assert_eq!(
+            url_fits(&url[..42], &ErrorCorrectionLevel::Medium, 3),
+            Ok(())
+        );
+        assert_eq!(url_fits(url, &ErrorCorrectionLevel::Medium, 4), Ok(()));
+    }
 }
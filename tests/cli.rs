@@ -0,0 +1,68 @@
+#![cfg(feature = "build-binary")]
+
+use qrust::qr::decode::from_image;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn run_qrgen(args: &[&str], input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_qrgen"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn output_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("qrgen-{}-{}", std::process::id(), name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn test_json_info() {
+    let output = output_path("json.png");
+    let stdout = run_qrgen(&["-o", &output, "--info", "--json"], "HELLO WORLD");
+    std::fs::remove_file(&output).unwrap();
+    let info: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(info["version"], 1);
+    assert_eq!(info["ecl"], "Medium");
+    assert_eq!(info["mode"], "Alphanumeric");
+    assert_eq!(info["data_codewords"], 16);
+    // 21 modules plus a quiet zone of 4 on each side, at 4 pixels per module
+    assert_eq!(info["width"], 29 * 4);
+    assert!(info["mask"].as_u64().unwrap() < 8);
+}
+
+#[test]
+fn test_base64_input() {
+    let output = output_path("base64.png");
+    // 0x00 0x7F 0x80 0xFF, which isn't valid UTF-8
+    run_qrgen(&["-o", &output, "--input-encoding", "base64"], "AH+A/w==\n");
+    let img = image::open(&output).unwrap().to_rgb();
+    std::fs::remove_file(&output).unwrap();
+    // the decoder falls back to ISO-8859-1 for bytes that aren't UTF-8
+    assert_eq!(
+        from_image(&img, Some(4)).unwrap(),
+        "\u{0}\u{7f}\u{80}\u{ff}"
+    );
+}
+
+#[test]
+fn test_no_trim() {
+    let output = output_path("no-trim.png");
+    run_qrgen(&["-o", &output, "--no-trim"], "secret ");
+    let img = image::open(&output).unwrap().to_rgb();
+    std::fs::remove_file(&output).unwrap();
+    assert_eq!(from_image(&img, Some(4)).unwrap(), "secret ");
+}